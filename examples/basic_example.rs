@@ -1,8 +1,11 @@
 extern crate glium;
 
-#[macro_use]
 extern crate ascii_opengl_rust;
 
+use ascii_opengl_rust::engine::core::{ EngineBuilder, Game, GameState };
+use ascii_opengl_rust::engine::object::Upload;
+use device_query::DeviceState;
+
 //------------------ My stuff --------------------------
 mod basic_example_res;
 use basic_example_res::game_init::game_init;
@@ -10,17 +13,45 @@ use basic_example_res::game_loop::game_loop;
 use basic_example_res::game_event::GameEvent;
 // -----------------------------------------------------
 
-fn main() {
-    let mut pause = false;
+/// Everything the example keeps between frames
+#[derive(Default)]
+struct BasicExample {
+    pause: bool,
+    last_keys: Vec<device_query::Keycode>,
+    game_events: Vec<GameEvent>,
+}
 
-    let mut last_keys: Vec<device_query::Keycode> = Vec::new();
+impl GameState for BasicExample {
+    fn init(&mut self, terminal_res: (u32, u32), game: &mut Game, display: &dyn Upload) {
+        game_init(terminal_res, game, display);
+    }
 
-    let mut game_events: Vec<GameEvent> = Vec::new();
+    fn fixed_update(
+        &mut self,
+        device_state: Option<&DeviceState>,
+        terminal_res: (u32, u32),
+        game: &mut Game,
+        display: &dyn Upload
+    ) {
+        game_loop(
+            device_state,
+            terminal_res,
+            game,
+            display,
+            &mut self.pause,
+            &mut self.game_events,
+            &mut self.last_keys
+        );
+    }
+}
 
+fn main() {
     // Main loop
-    init_engine!(
-        game_loop!(game_loop, &mut pause, &mut game_events, &mut last_keys),
-        game_init!(game_init),
-        "examples/basic_example_res/assets"
-    );
+    match EngineBuilder::new("examples/basic_example_res/assets").run(BasicExample::default()) {
+        Ok(code) => std::process::exit(code),
+        Err(error) => {
+            eprintln!("Failed to start the engine: {}", error);
+            std::process::exit(1);
+        }
+    }
 }
@@ -13,5 +13,5 @@ out vec2 v_tex_coords;
 void main() {
   v_tex_coords = tex_coords;
   
-  gl_Position = vec4(position.x-0.5, position.y-1.0, position.z, 1.0);
+  gl_Position = vec4(position.x*2.0-1.0, position.y-1.0, position.z, 1.0);
 }
\ No newline at end of file
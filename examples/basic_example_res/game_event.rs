@@ -2,6 +2,7 @@
 
 use device_query::Keycode;
 
+#[allow(dead_code)]
 pub enum GameEvent{
     KeyUp(KeyUpEvent),
     KeyDown(KeyDownEvent),
@@ -11,6 +12,7 @@ pub struct KeyUpEvent{
     pub key: Keycode,
 }
 
+#[allow(dead_code)]
 pub struct KeyDownEvent{
     pub key: Keycode,
 }
\ No newline at end of file
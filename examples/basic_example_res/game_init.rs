@@ -1,11 +1,13 @@
 use ascii_opengl_rust::engine::core::Game;
+use ascii_opengl_rust::engine::glyph::GlyphTable;
 use ascii_opengl_rust::engine::matrices::model_matrix;
-use ascii_opengl_rust::engine::object::{ Object, TextureFilter };
+use ascii_opengl_rust::engine::object::{ Object, TextureFilter, Upload };
 use ascii_opengl_rust::engine::scene::Scene;
 use ascii_opengl_rust::engine::ui::{ draw_rect, draw_text };
 use fontdue::Font;
+use std::sync::Arc;
 
-pub fn game_init(terminal_res: (u32, u32), game: &mut Game, display: &glium::Display) {
+pub fn game_init(terminal_res: (u32, u32), game: &mut Game, display: &dyn Upload) {
     let mut scene: Scene = Scene::new();
 
     game.add_scene(
@@ -20,24 +22,20 @@ pub fn game_init(terminal_res: (u32, u32), game: &mut Game, display: &glium::Dis
             model_matrix(&[0.0, 0.0, 2.0], &[0.0, 0.0, 0.0], &[1.0, 1.0, 1.0]),
             display,
             vec!["monke".to_string()]
-        )
+        ).unwrap()
     );
 
     game.add_scene(scene);
 
-    display
-        .gl_window()
-        .window()
-        .set_cursor_position(
-            glium::glutin::dpi::PhysicalPosition::new(
-                (terminal_res.0 as f64) / 2.0,
-                (terminal_res.1 as f64) / 2.0
-            )
-        )
-        .unwrap();
+    game.set_cursor_position((terminal_res.0 as f64) / 2.0, (terminal_res.1 as f64) / 2.0);
 
     game.camera.player_rot = [0.0, 0.0, 0.0];
 
+    // glyphs for CellMode::Shape, built with `cargo run --example glyph_table`
+    game.output.glyph_table = Some(
+        Arc::new(GlyphTable::load(format!("{}/glyphs/roboto.json", game.assets_path)).unwrap())
+    );
+
     // let mut scene = Scene::new();
 
     // scene.add_object(Object::new(
@@ -83,13 +81,14 @@ pub fn game_init(terminal_res: (u32, u32), game: &mut Game, display: &glium::Dis
         fontdue::FontSettings::default()
     ).unwrap();
 
-    game.add_ui_elems(draw_text(0.0, 0.0, "Text", 3.0, &font, display));
+    game.add_ui_elems(draw_text(0.0, 0.0, "Text", 3.0, &font, display).unwrap());
     // game.add_ui_elems(draw_text(0.0, 0.5, "b", 20.0, &font, &display));
 
     // return;
 
-    game.add_ui_elem(
-        draw_rect(
+    // the game keeps running without the sprite if it can not be loaded
+    if
+        let Ok(rect) = draw_rect(
             0.0,
             0.5,
             0.25,
@@ -98,5 +97,7 @@ pub fn game_init(terminal_res: (u32, u32), game: &mut Game, display: &glium::Dis
             TextureFilter::Linear,
             display
         )
-    );
+    {
+        game.add_ui_elem(rect);
+    }
 }
@@ -1,13 +1,16 @@
 // WIP
+use ascii_opengl_rust::engine::ascii_render::CellMode;
 use ascii_opengl_rust::engine::core::Game;
+use ascii_opengl_rust::engine::edges::EdgeSettings;
+use ascii_opengl_rust::engine::object::Upload;
 use device_query::{ DeviceQuery, DeviceState, Keycode };
 use crate::basic_example_res::game_event::{ GameEvent, KeyDownEvent, KeyUpEvent };
 
 pub fn game_loop(
-    device_state: &DeviceState,
+    device_state: Option<&DeviceState>,
     terminal_res: (u32, u32),
     game: &mut Game,
-    display: &glium::Display,
+    _display: &dyn Upload,
     pause: &mut bool,
     game_events: &mut Vec<GameEvent>,
     last_keys: &mut Vec<Keycode>
@@ -17,19 +20,20 @@ pub fn game_loop(
     let mut move_vector = [0, 0, 0];
     let mut mouse_vector = [0, 0];
 
-    let keys: Vec<Keycode> = device_state.get_keys();
+    // nothing is pressed when the keyboard can not be read
+    let keys: Vec<Keycode> = device_state.map_or_else(Vec::new, |device_state| device_state.get_keys());
 
     // keys handler -----------------------------------------------------------------------
 
     keys.iter().for_each(|key| {
         if !last_keys.contains(key) {
-            game_events.push(GameEvent::KeyDown(KeyDownEvent { key: key.clone() }));
+            game_events.push(GameEvent::KeyDown(KeyDownEvent { key: *key }));
         }
     });
 
     last_keys.iter().for_each(|key| {
         if !keys.contains(key) {
-            game_events.push(GameEvent::KeyUp(KeyUpEvent { key: key.clone() }));
+            game_events.push(GameEvent::KeyUp(KeyUpEvent { key: *key }));
         }
     });
 
@@ -37,7 +41,11 @@ pub fn game_loop(
 
     // mouse handler ----------------------------------------------------------------------
 
-    let mouse_pos = device_state.get_mouse().coords;
+    // the mouse stays in the middle when it can not be read
+    let mouse_pos = device_state.map_or(
+        ((terminal_res.0 / 2) as i32, (terminal_res.1 / 2) as i32),
+        |device_state| device_state.get_mouse().coords
+    );
 
     let mut mouse_delta = (mouse_pos.0 as f32 / terminal_res.0 as f32 - 0.5, mouse_pos.1 as f32 / terminal_res.1 as f32 - 0.5);
 
@@ -64,22 +72,50 @@ pub fn game_loop(
     // game events ------------------------------------------------------------------------
 
     game_events.iter().for_each(|event| {
-        match event {
-            GameEvent::KeyUp(event) => {
-                match event.key {
-                    Keycode::Escape => {
-                        *pause = !*pause;
-                    }
-                    Keycode::Q => {
-                        game.set_scene(0);
-                    }
-                    Keycode::E => {
-                        game.set_scene(1);
+        if let GameEvent::KeyUp(event) = event {
+            match event.key {
+                Keycode::Escape => {
+                    *pause = !*pause;
+                }
+                Keycode::X => {
+                    game.request_exit();
+                }
+                Keycode::Q => {
+                    game.set_scene(0);
+                }
+                Keycode::E => {
+                    game.set_scene(1);
+                }
+                Keycode::M => {
+                    game.output.cell_mode = match game.output.cell_mode {
+                        CellMode::Ascii => CellMode::HalfBlock,
+                        CellMode::HalfBlock => CellMode::Braille,
+                        CellMode::Braille => CellMode::Shape,
+                        CellMode::Shape => CellMode::Ascii,
+                    };
+                }
+                Keycode::R => {
+                    if game.get_recording_path().is_some() {
+                        game.stop_recording();
+                    } else {
+                        game.start_recording("recording.cast");
                     }
-                    _ => (),
                 }
+                Keycode::O => {
+                    game.output.edges = match game.output.edges {
+                        Some(_) => None,
+                        None => Some(EdgeSettings::default()),
+                    };
+                }
+                Keycode::P => {
+                    game.output.supersampling = match game.output.supersampling {
+                        (1, 1) => (2, 2),
+                        (2, 2) => (4, 4),
+                        _ => (1, 1),
+                    };
+                }
+                _ => (),
             }
-            _ => (),
         }
     });
 
@@ -127,21 +163,12 @@ pub fn game_loop(
         game.camera.update_by_speed(terminal_res, move_vector, mouse_vector);
 
         //rotate the camera
-        game.camera.player_rot[0] += (mouse_delta.1 as f32) * 0.4;
-        game.camera.player_rot[1] += (mouse_delta.0 as f32) * 0.4 * aspect_ratio;
+        game.camera.player_rot[0] += mouse_delta.1 * 0.4;
+        game.camera.player_rot[1] += mouse_delta.0 * 0.4 * aspect_ratio;
 
         game.camera.update_self(terminal_res);
 
-        display
-            .gl_window()
-            .window()
-            .set_cursor_position(
-                glium::glutin::dpi::PhysicalPosition::new(
-                    (terminal_res.0 as f64) / 2.0,
-                    (terminal_res.1 as f64) / 2.0
-                )
-            )
-            .unwrap();
+        game.set_cursor_position((terminal_res.0 as f64) / 2.0, (terminal_res.1 as f64) / 2.0);
     }
 
     //clean up ---------------------------------------------------------------------------
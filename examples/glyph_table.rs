@@ -0,0 +1,24 @@
+extern crate ascii_opengl_rust;
+
+use ascii_opengl_rust::engine::glyph::GlyphTable;
+use fontdue::Font;
+
+/// Rasterizes the glyph table used by `CellMode::Shape` ahead of time.
+/// Usage: `cargo run --example glyph_table -- [font.ttf] [table.json]`
+fn main() {
+    let mut args = std::env::args().skip(1);
+    let font_path = args
+        .next()
+        .unwrap_or_else(|| "examples/basic_example_res/assets/fonts/Roboto-Regular.ttf".to_string());
+    let table_path = args
+        .next()
+        .unwrap_or_else(|| "examples/basic_example_res/assets/glyphs/roboto.json".to_string());
+
+    let font_data = std::fs::read(&font_path).expect("Failed to read font file");
+    let font = Font::from_bytes(font_data, fontdue::FontSettings::default()).unwrap();
+
+    let table = GlyphTable::build(&font, GlyphTable::PRINTABLE_ASCII);
+    table.save(&table_path).expect("Failed to write glyph table");
+
+    println!("Wrote {} glyphs from {} to {}", table.glyphs().len(), font_path, table_path);
+}
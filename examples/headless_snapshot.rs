@@ -0,0 +1,47 @@
+extern crate ascii_opengl_rust;
+
+use ascii_opengl_rust::engine::ascii_render::{ CellMode, OutputSettings };
+use ascii_opengl_rust::engine::camera::Camera;
+use ascii_opengl_rust::engine::core::Game;
+use ascii_opengl_rust::engine::export;
+use ascii_opengl_rust::engine::headless::HeadlessRenderer;
+use ascii_opengl_rust::engine::scene::Scene;
+
+/// Renders the example scene without a terminal or window and writes it as HTML, e.g. for snapshot tests in CI.
+/// Usage: `cargo run --example headless_snapshot -- [columns] [rows] [snapshot.html]`
+fn main() {
+    let mut args = std::env::args().skip(1);
+    let columns: usize = args.next().map_or(80, |value| value.parse().expect("columns must be a number"));
+    let rows: usize = args.next().map_or(24, |value| value.parse().expect("rows must be a number"));
+    let snapshot_path = args.next().unwrap_or_else(|| "snapshot.html".to_string());
+
+    let assets_path = "examples/basic_example_res/assets";
+    let mut renderer = HeadlessRenderer::new(assets_path);
+
+    let camera = Camera::new([0.0, 0.0, 0.0f32], [0.0, 0.0, 0.0f32], 0.05, 0.05, (columns as u32, rows as u32));
+    let mut game = Game::new(camera, assets_path.to_string());
+    // fixed settings instead of the detected ones, so snapshots match on every machine
+    game.output = OutputSettings {
+        cell_mode: CellMode::HalfBlock,
+        ..Default::default()
+    };
+    game.add_scene(Scene::load_from_json("/scenes/scene1.json", assets_path, &renderer).unwrap());
+
+    let frame = renderer.render(&game, &game.camera, columns, rows);
+    let page = export::html(
+        &frame.cells,
+        frame.cells.len() / frame.rows,
+        game.output.cell_mode.columns_per_cell(),
+        game.output.color_depth,
+    );
+    std::fs::write(&snapshot_path, page).expect("Failed to write snapshot");
+
+    println!(
+        "Rendered {} cells ({}x{} pixels, {}) to {}",
+        frame.cells.len(),
+        frame.pixel_width,
+        frame.pixel_height,
+        if renderer.is_opengl() { "OpenGL" } else { "software" },
+        snapshot_path
+    );
+}
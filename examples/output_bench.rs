@@ -0,0 +1,206 @@
+extern crate ascii_opengl_rust;
+
+use ascii_opengl_rust::engine::ascii_render::{CellMode, Color, OutputSettings, TerminalFrameBuffer};
+use std::io::Write;
+use std::time::{Duration, Instant};
+
+const COLUMNS: usize = 160;
+const ROWS: usize = 48;
+const FRAMES: usize = 120;
+
+/// Sky with a lit sphere moving across it, similar to what `basic_example` renders
+fn scene(x: f32, y: f32, time: f32) -> Color {
+    let center = (0.5 + 0.3 * time.sin(), 0.5 + 0.1 * (time * 2.0).cos());
+    let (dx, dy) = ((x - center.0) * 2.0, (y - center.1) * 2.0);
+    let distance = dx * dx + dy * dy;
+
+    if distance < 0.25 {
+        let z = (0.25 - distance).sqrt() * 2.0;
+        let light = (dx * -0.5 + dy * -0.5 + z * 0.7).max(0.1);
+        let shade = (light * 255.0).min(255.0) as u8;
+        Color { r: shade, g: shade / 2, b: shade / 3 }
+    } else {
+        Color { r: 105, g: 109, b: 219 }
+    }
+}
+
+/// Colors of every frame of the scene on a `width` x `height` framebuffer, row by row
+fn frames(width: usize, height: usize) -> Vec<Vec<Color>> {
+    (0..FRAMES)
+        .map(|frame| {
+            (0..width * height)
+                .map(|i| scene((i % width) as f32 / width as f32, (i / width) as f32 / height as f32, frame as f32 * 0.05))
+                .collect()
+        })
+        .collect()
+}
+
+/// Everything written for all frames
+fn output(settings: &OutputSettings) -> Vec<u8> {
+    let (width, height) = CellMode::Ascii.pixel_size(COLUMNS, ROWS);
+    let mut framebuffer =
+        TerminalFrameBuffer::with_writer(width, height, Color { r: 0, g: 0, b: 0 }, Vec::new());
+    framebuffer.set_settings(settings.clone());
+    framebuffer.get_writer_mut().clear();
+
+    let (width, height) = framebuffer.size();
+    for frame in frames(width, height) {
+        framebuffer.clear();
+        for (i, color) in frame.into_iter().enumerate() {
+            framebuffer.set_pixel(i % width, i / width, color);
+        }
+        framebuffer.draw_frame().unwrap();
+    }
+
+    framebuffer.into_writer()
+}
+
+/// Everything the `draw_frame` the engine started out with wrote for all frames, in ASCII mode and truecolor only.
+/// Kept as the reference the encoder is measured against.
+fn baseline_output() -> Vec<u8> {
+    let characters = ["@", "#", "S", "%", "?", "*", "+", ";", ":", ",", ".", "\u{a0}"];
+    let (width, height) = CellMode::Ascii.pixel_size(COLUMNS, ROWS);
+    let mut front = vec![u32::MAX; width * height];
+    let mut out = Vec::new();
+
+    for frame in frames(width, height) {
+        for y in 0..height {
+            for x in 0..width {
+                let pixel = u32::from(frame[y * width + x]);
+                if front[y * width + x] == pixel {
+                    continue;
+                }
+                front[y * width + x] = pixel;
+
+                let Color { r, g, b } = frame[y * width + x];
+                let (h, s, l) = rgb_to_hsl(f32::from(r) / 255.0, f32::from(g) / 255.0, f32::from(b) / 255.0);
+                let character = characters[((1.0 - l) * ((characters.len() - 1) as f32)) as usize];
+                let (fr, fg, fb) = hsl_to_rgb(h, s, 0.5);
+                write!(
+                    out,
+                    "\x1B[{};{}H\x1b[48;2;{};{};{}m\x1b[38;2;{};{};{}m{}{}",
+                    y + 1,
+                    x * 2 + 1,
+                    r,
+                    g,
+                    b,
+                    fr,
+                    fg,
+                    fb,
+                    character,
+                    character
+                )
+                .unwrap();
+            }
+        }
+        write!(out, "\x1B[{};{}H\x1b[0m", height + 1, 1).unwrap();
+    }
+
+    out
+}
+
+fn rgb_to_hsl(r: f32, g: f32, b: f32) -> (f32, f32, f32) {
+    let cmax = r.max(g.max(b));
+    let cmin = r.min(g.min(b));
+    let delta = cmax - cmin;
+
+    let h = if delta == 0.0 {
+        0.0
+    } else if cmax == r {
+        60.0 * (((g - b) / delta) % 6.0)
+    } else if cmax == g {
+        60.0 * ((b - r) / delta + 2.0)
+    } else {
+        60.0 * ((r - g) / delta + 4.0)
+    };
+
+    let l = (cmax + cmin) / 2.0;
+    let s = if delta == 0.0 { 0.0 } else { delta / (1.0 - (2.0 * l - 1.0).abs()) };
+
+    (h, s, l)
+}
+
+fn hsl_to_rgb(h: f32, s: f32, l: f32) -> (u8, u8, u8) {
+    let c = (1.0 - (2.0 * l - 1.0).abs()) * s;
+    let x = c * (1.0 - (((h / 60.0) % 2.0) - 1.0).abs());
+    let m = l - c / 2.0;
+
+    let (r, g, b) = if h < 60.0 {
+        (c, x, 0.0)
+    } else if h < 120.0 {
+        (x, c, 0.0)
+    } else if h < 180.0 {
+        (0.0, c, x)
+    } else if h < 240.0 {
+        (0.0, x, c)
+    } else if h < 300.0 {
+        (x, 0.0, c)
+    } else {
+        (c, 0.0, x)
+    };
+
+    (((r + m) * 255.0) as u8, ((g + m) * 255.0) as u8, ((b + m) * 255.0) as u8)
+}
+
+/// Average time `draw_frame` takes on a `columns` x `rows` terminal
+fn frame_time(settings: &OutputSettings, columns: usize, rows: usize) -> Duration {
+    let (width, height) = CellMode::Ascii.pixel_size(columns, rows);
+    let mut framebuffer =
+        TerminalFrameBuffer::with_writer(width, height, Color { r: 0, g: 0, b: 0 }, Vec::new());
+    framebuffer.set_settings(settings.clone());
+
+    let (width, height) = framebuffer.size();
+    let mut total = Duration::ZERO;
+    for frame in 0..FRAMES {
+        framebuffer.clear();
+        for y in 0..height {
+            for x in 0..width {
+                let color = scene(x as f32 / width as f32, y as f32 / height as f32, frame as f32 * 0.05);
+                framebuffer.set_pixel(x, y, color);
+            }
+        }
+        framebuffer.get_writer_mut().clear();
+        let start = Instant::now();
+        framebuffer.draw_frame().unwrap();
+        total += start.elapsed();
+    }
+
+    total / FRAMES as u32
+}
+
+fn main() {
+    println!("{}x{} terminal, {} frames", COLUMNS, ROWS, FRAMES);
+
+    let baseline = baseline_output();
+    let plain = output(&OutputSettings { cell_mode: CellMode::Ascii, coalesce: false, ..Default::default() });
+    assert!(plain == baseline, "uncoalesced ASCII output differs from the baseline draw_frame");
+    println!("uncoalesced ASCII output is the baseline draw_frame output byte for byte");
+    println!();
+
+    println!("{:<10} {:>14} {:>14} {:>8}", "mode", "plain B/f", "coalesced B/f", "saved");
+    for cell_mode in [CellMode::Ascii, CellMode::HalfBlock, CellMode::Braille] {
+        let plain = output(&OutputSettings { cell_mode, coalesce: false, ..Default::default() }).len() / FRAMES;
+        let coalesced = output(&OutputSettings { cell_mode, coalesce: true, ..Default::default() }).len() / FRAMES;
+        println!(
+            "{:<10} {:>14} {:>14} {:>7.1}%",
+            format!("{:?}", cell_mode),
+            plain,
+            coalesced,
+            100.0 - coalesced as f32 * 100.0 / plain as f32
+        );
+    }
+
+    println!();
+    println!("300x90 terminal, draw_frame time");
+    println!("{:<10} {:>14} {:>14}", "mode", "serial ms/f", "parallel ms/f");
+    for cell_mode in [CellMode::Ascii, CellMode::HalfBlock, CellMode::Braille] {
+        let serial = frame_time(&OutputSettings { cell_mode, parallel: false, ..Default::default() }, 300, 90);
+        let parallel = frame_time(&OutputSettings { cell_mode, parallel: true, ..Default::default() }, 300, 90);
+        println!(
+            "{:<10} {:>14.3} {:>14.3}",
+            format!("{:?}", cell_mode),
+            serial.as_secs_f64() * 1000.0,
+            parallel.as_secs_f64() * 1000.0
+        );
+    }
+}
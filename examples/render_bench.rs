@@ -0,0 +1,81 @@
+extern crate ascii_opengl_rust;
+
+use ascii_opengl_rust::engine::ascii_render::{CellMode, OutputSettings};
+use ascii_opengl_rust::engine::camera::Camera;
+use ascii_opengl_rust::engine::core::Game;
+use ascii_opengl_rust::engine::headless::HeadlessRenderer;
+use ascii_opengl_rust::engine::render::RenderSettings;
+use ascii_opengl_rust::engine::scene::Scene;
+use std::time::{Duration, Instant};
+
+const FRAMES: usize = 120;
+
+/// Average time per frame of rendering `game` at the given terminal sizes, one after another.
+/// Without `cached` the render targets are allocated for every frame, as before they were kept between frames.
+fn frame_time(renderer: &mut HeadlessRenderer, game: &Game, sizes: &[(usize, usize)], cached: bool) -> Duration {
+    let start = Instant::now();
+    for frame in 0..FRAMES {
+        if !cached {
+            renderer.release_targets();
+        }
+        let (columns, rows) = sizes[frame % sizes.len()];
+        renderer.render(game, &game.camera, columns, rows);
+    }
+    start.elapsed() / FRAMES as u32
+}
+
+/// Measures how long rendering a frame of the example scene takes, with the render targets cached or allocated
+/// every frame, and with cached targets with and without the terminal being resized and with and without
+/// `OutputSettings::readback_latency`.
+/// Usage: `cargo run --release --example render_bench -- [columns] [rows]`
+fn main() {
+    let mut args = std::env::args().skip(1);
+    let columns: usize = args.next().map_or(160, |value| value.parse().expect("columns must be a number"));
+    let rows: usize = args.next().map_or(48, |value| value.parse().expect("rows must be a number"));
+
+    let assets_path = "examples/basic_example_res/assets";
+    // the software renderer has no readback to overlap with the next frame, only OpenGL is worth measuring
+    let mut renderer = match HeadlessRenderer::opengl(assets_path, &RenderSettings::default()) {
+        Ok(renderer) => renderer,
+        Err(error) => {
+            eprintln!("render_bench needs OpenGL: {}", error);
+            std::process::exit(1);
+        }
+    };
+
+    let camera = Camera::new([0.0, 0.0, 0.0f32], [0.0, 0.0, 0.0f32], 0.05, 0.05, (columns as u32, rows as u32));
+    let mut game = Game::new(camera, assets_path.to_string());
+    game.output = OutputSettings {
+        cell_mode: CellMode::HalfBlock,
+        ..Default::default()
+    };
+    game.add_scene(Scene::load_from_json("/scenes/scene1.json", assets_path, &renderer).unwrap());
+
+    println!("{}x{} terminal, {} frames", columns, rows, FRAMES);
+
+    // the first frame compiles shaders and allocates the render targets
+    renderer.render(&game, &game.camera, columns, rows);
+
+    // a smaller size for the resize rows, never zero so the render targets can be allocated
+    let smaller = (columns.saturating_sub(2).max(1), rows.saturating_sub(1).max(1));
+
+    // releasing the targets also drops the frame still waiting to be read back, so this is only measured without latency
+    let mut results = vec![(
+        "targets allocated every frame".to_string(),
+        frame_time(&mut renderer, &game, &[(columns, rows)], false),
+    )];
+    for readback_latency in [false, true] {
+        game.output.readback_latency = readback_latency;
+        let latency = if readback_latency { ", 1 frame latency" } else { "" };
+        results.push((format!("same size{}", latency), frame_time(&mut renderer, &game, &[(columns, rows)], true)));
+        results.push((
+            format!("resized every frame{}", latency),
+            frame_time(&mut renderer, &game, &[(columns, rows), smaller], true),
+        ));
+    }
+
+    println!("{:<48} {:>10} {:>10}", "", "ms/frame", "frames/s");
+    for (name, time) in results {
+        println!("{:<48} {:>10.2} {:>10.1}", name, time.as_secs_f64() * 1000.0, 1.0 / time.as_secs_f64());
+    }
+}
@@ -1,14 +1,266 @@
+use super::edges::{ Edge, EdgeSettings };
+use super::encoder::AnsiEncoder;
+use super::export;
+use super::glyph::{ GlyphRamp, GlyphTable, LuminanceMetric };
+use super::palette::{ self, ColorDepth, Dither };
+use super::recorder::AsciicastRecorder;
+use rayon::prelude::*;
 use std::io::{ BufWriter, Write, stdout };
+use std::ops::Range;
+use std::sync::Arc;
 
-pub struct TerminalFrameBuffer {
+/// Pixel value that never matches a packed color, used to force a cell to be redrawn.
+const INVALID_PIXEL: u32 = 0xff00_0000;
+
+/// Cell rows converted together by one thread in `draw_frame`
+const BAND_ROWS: usize = 8;
+
+/// How pixels of the framebuffer are laid out on terminal cells.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Default)]
+pub enum CellMode {
+    /// One pixel per cell, drawn as two glyphs picked from the glyph ramp.
+    #[default]
+    Ascii,
+    /// Two vertically stacked pixels per cell, drawn with the upper half block (`▀`).
+    HalfBlock,
+    /// A 2x4 block of pixels per cell, drawn as a Braille pattern (U+2800 - U+28FF).
+    Braille,
+    /// A 4x8 block of pixels per cell, drawn with the glyph of `OutputSettings::glyph_table` whose shape matches best
+    /// in the two most distinct colors of the block.
+    Shape,
+}
+
+impl CellMode {
+    /// Size of one cell in framebuffer pixels as `(width, height)`.
+    pub fn cell_size(&self) -> (usize, usize) {
+        match self {
+            CellMode::Ascii => (1, 1),
+            CellMode::HalfBlock => (1, 2),
+            CellMode::Braille => (2, 4),
+            CellMode::Shape => (4, 8),
+        }
+    }
+
+    /// Number of terminal columns a single cell occupies.
+    pub fn columns_per_cell(&self) -> usize {
+        match self {
+            CellMode::Ascii => 2,
+            CellMode::HalfBlock | CellMode::Braille | CellMode::Shape => 1,
+        }
+    }
+
+    /// Framebuffer size in pixels for a terminal of `columns` x `rows`.
+    pub fn pixel_size(&self, columns: usize, rows: usize) -> (usize, usize) {
+        let (cell_width, cell_height) = self.cell_size();
+        ((columns / self.columns_per_cell()) * cell_width, rows * cell_height)
+    }
+}
+
+/// How `CellMode::Braille` decides which dots of a cell are raised.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum BrailleStyle {
+    /// Raise a dot when the pixel luminance is above the given value (0.0 - 1.0).
+    Threshold(f32),
+    /// Raise a dot by comparing the pixel luminance against a 2x4 ordered (Bayer) matrix.
+    Ordered,
+}
+
+impl Default for BrailleStyle {
+    fn default() -> Self {
+        BrailleStyle::Threshold(0.5)
+    }
+}
+
+/// Ordered dither thresholds for the 2x4 dots of a Braille cell, indexed as `[y][x]`.
+const BRAILLE_BAYER: [[f32; 2]; 4] = [
+    [0.5 / 8.0, 4.5 / 8.0],
+    [6.5 / 8.0, 2.5 / 8.0],
+    [1.5 / 8.0, 5.5 / 8.0],
+    [7.5 / 8.0, 3.5 / 8.0],
+];
+
+/// Bit of the Braille pattern for the dot at `[y][x]`.
+const BRAILLE_DOTS: [[u32; 2]; 4] = [
+    [0x01, 0x08],
+    [0x02, 0x10],
+    [0x04, 0x20],
+    [0x40, 0x80],
+];
+
+/// Keeps the glyph of a cell while its luminance only wavers around a boundary of the glyph choice.
+/// A new glyph shows up when either rule lets it, with both turned off every change shows up right away.
+#[derive(Copy, Clone, Debug, PartialEq, Default)]
+pub struct Hysteresis {
+    /// Luminance (0.0 - 1.0) past the boundary before the glyph changes, 0.0 turns it off.
+    /// Used for the glyph ramp and Braille dots, `CellMode::Shape` with a glyph table only uses `frames`.
+    pub margin: f32,
+    /// Frames in a row the new glyph has to be picked before it changes, 0 turns it off
+    pub frames: u32,
+}
+
+/// `Hysteresis` for every cell mode that picks glyphs, `CellMode::HalfBlock` always draws the same one.
+#[derive(Copy, Clone, Debug, PartialEq, Default)]
+pub struct GlyphHysteresis {
+    pub ascii: Hysteresis,
+    pub braille: Hysteresis,
+    pub shape: Hysteresis,
+}
+
+impl GlyphHysteresis {
+    /// The hysteresis used in `mode`
+    pub fn for_mode(&self, mode: CellMode) -> Hysteresis {
+        match mode {
+            CellMode::Ascii => self.ascii,
+            CellMode::Braille => self.braille,
+            CellMode::Shape => self.shape,
+            CellMode::HalfBlock => Hysteresis::default(),
+        }
+    }
+}
+
+/// Glyph a cell shows without edges and the one waiting to replace it, see `GlyphHysteresis`
+#[derive(Copy, Clone, Debug, Default)]
+struct CellGlyph {
+    /// `None` when unknown, e.g. after `invalidate`
+    shown: Option<char>,
+    candidate: Option<char>,
+    /// Frames in a row `candidate` was picked
+    frames: u32,
+}
+
+/// Characters the terminal is able to print.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Default)]
+pub enum GlyphSet {
+    #[default]
+    Unicode,
+    /// Plain ASCII only, for terminals and locales without UTF-8
+    Ascii,
+}
+
+/// Settings that control how the framebuffer is turned into terminal output.
+#[derive(Clone, Debug, PartialEq)]
+pub struct OutputSettings {
+    pub cell_mode: CellMode,
+    pub braille_style: BrailleStyle,
+    pub color_depth: ColorDepth,
+    /// Dithering used when `color_depth` is not `ColorDepth::TrueColor`
+    pub dither: Dither,
+    pub glyph_set: GlyphSet,
+    /// Glyphs `CellMode::Ascii` picks from
+    pub glyph_ramp: GlyphRamp,
+    /// Glyphs `CellMode::Shape` picks from, without one it falls back to `glyph_ramp`
+    pub glyph_table: Option<Arc<GlyphTable>>,
+    /// How luminance is measured for the glyph ramp and Braille dots
+    pub luminance: LuminanceMetric,
+    /// Exponent applied to the luminance before it is used, values below 1.0 brighten the midtones
+    pub gamma: f32,
+    /// Leave out cursor moves and colors the terminal already has, see `AnsiEncoder`
+    pub coalesce: bool,
+    /// Outline pass drawing line glyphs on depth and normal edges, off when `None`
+    pub edges: Option<EdgeSettings>,
+    /// Pixels rendered per framebuffer pixel horizontally and vertically, averaged into one.
+    /// Framebuffer pixels are the subdivisions of a cell given by `CellMode::cell_size`, e.g. the top and bottom half in `CellMode::HalfBlock`.
+    pub supersampling: (usize, usize),
+    /// Show every frame one frame late, so reading it back from the GPU overlaps with rendering the next one
+    pub readback_latency: bool,
+    /// Cells keep what the terminal shows while their glyph is the same and their colors are at most this far
+    /// from the shown ones, see `palette::perceptual_distance`. 0.0 redraws every change, around 20.0 hides lighting noise.
+    pub color_tolerance: f32,
+    /// Keeps glyphs from flipping back and forth when the luminance hovers around a boundary, off by default
+    pub glyph_hysteresis: GlyphHysteresis,
+    /// Convert bands of cell rows on all cores, the output is the same as converting them one after another
+    pub parallel: bool,
+}
+
+impl Default for OutputSettings {
+    fn default() -> Self {
+        OutputSettings {
+            cell_mode: CellMode::default(),
+            braille_style: BrailleStyle::default(),
+            color_depth: ColorDepth::default(),
+            dither: Dither::default(),
+            glyph_set: GlyphSet::default(),
+            glyph_ramp: GlyphRamp::default(),
+            glyph_table: None,
+            luminance: LuminanceMetric::default(),
+            gamma: 1.0,
+            coalesce: true,
+            edges: None,
+            supersampling: (1, 1),
+            readback_latency: false,
+            color_tolerance: 0.0,
+            glyph_hysteresis: GlyphHysteresis::default(),
+            parallel: true,
+        }
+    }
+}
+
+/// How a frame is handed to the terminal, so it is never painted half updated.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Default)]
+pub enum FrameSync {
+    /// Wrap every frame in a synchronized update (DEC private mode 2026), the terminal paints it once it is complete
+    Synchronized,
+    /// Build every frame in one buffer and write it at once, for terminals without synchronized output
+    #[default]
+    SingleWrite,
+    /// Write every band of rows on its own, the terminal may paint a frame half updated
+    Streamed,
+}
+
+/// Begins a synchronized update
+const BEGIN_SYNCHRONIZED_UPDATE: &[u8] = b"\x1b[?2026h";
+/// Ends a synchronized update
+const END_SYNCHRONIZED_UPDATE: &[u8] = b"\x1b[?2026l";
+
+/// What a cleared terminal shows
+const BLANK_CELL: Cell = Cell {
+    glyph: ' ',
+    foreground: None,
+    background: None,
+};
+
+/// A single terminal cell as it is written out.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Cell {
+    pub glyph: char,
+    /// Foreground color, `None` for the terminal's default
+    pub foreground: Option<Color>,
+    /// Background color, `None` for the terminal's default
+    pub background: Option<Color>,
+}
+
+/// Writer used by `TerminalFrameBuffer` when no other sink is given
+pub type StdoutWriter = BufWriter<std::io::StdoutLock<'static>>;
+
+/// Double buffered image that is written to a terminal (or any other `Write` sink) as escape sequences.
+pub struct TerminalFrameBuffer<W: Write = StdoutWriter> {
     front_buffer: Vec<u32>,
     back_buffer: Vec<u32>,
+    /// Edges through the pixels of the front and back buffer, only used when `OutputSettings::edges` is set
+    front_edges: Vec<Edge>,
+    back_edges: Vec<Edge>,
     width: usize,
     height: usize,
-    out: BufWriter<std::io::StdoutLock<'static>>,
+    settings: OutputSettings,
+    encoder: AnsiEncoder,
+    /// Output of the current frame, written to `out` and the recorder in one go
+    pending: Vec<u8>,
+    /// The terminal is cleared at the start of the next frame
+    clear_screen: bool,
+    frame_sync: FrameSync,
+    /// Cells written by the last `draw_frame`
+    cells_written: usize,
+    /// Glyph history of every cell, row by row
+    glyphs: Vec<CellGlyph>,
+    /// Cells the terminal shows, row by row
+    shown_cells: Vec<Cell>,
+    recorder: Option<AsciicastRecorder>,
+    /// Why the last recording stopped on its own
+    recording_error: Option<std::io::Error>,
+    out: W,
 }
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub struct Color {
     pub r: u8,
     pub g: u8,
@@ -21,110 +273,777 @@ pub struct Color {
 //     }
 // }
 
+impl From<Color> for u32 {
+    fn from(color: Color) -> u32 {
+        (u32::from(color.r) << 16) | (u32::from(color.g) << 8) | u32::from(color.b)
+    }
+}
+
+impl From<u32> for Color {
+    fn from(pixel: u32) -> Color {
+        Color {
+            r: ((pixel >> 16) & 0xff) as u8,
+            g: ((pixel >> 8) & 0xff) as u8,
+            b: (pixel & 0xff) as u8,
+        }
+    }
+}
+
 impl TerminalFrameBuffer {
+    /// Creates a framebuffer that draws to stdout
     pub fn new(width: usize, height: usize, initial_color: Color) -> TerminalFrameBuffer {
-        let initial_color_value =
-            (u32::from(initial_color.r) << 16) |
-            (u32::from(initial_color.g) << 8) |
-            u32::from(initial_color.b);
+        TerminalFrameBuffer::with_writer(width, height, initial_color, BufWriter::new(stdout().lock()))
+    }
+}
+
+impl<W: Write> TerminalFrameBuffer<W> {
+    /// Creates a framebuffer that draws to `out`, e.g. a `Vec<u8>`, a file or a socket
+    pub fn with_writer(width: usize, height: usize, initial_color: Color, out: W) -> TerminalFrameBuffer<W> {
+        let initial_color_value = u32::from(initial_color);
         let mut framebuffer = TerminalFrameBuffer {
             front_buffer: vec![initial_color_value; width * height],
             back_buffer: vec![initial_color_value; width * height],
+            front_edges: vec![Edge::None; width * height],
+            back_edges: vec![Edge::None; width * height],
             width,
             height,
-            out: BufWriter::new(stdout().lock()),
+            settings: OutputSettings::default(),
+            encoder: AnsiEncoder::new(true, ColorDepth::default(), 0),
+            pending: Vec::new(),
+            clear_screen: false,
+            frame_sync: FrameSync::default(),
+            cells_written: 0,
+            glyphs: Vec::new(),
+            shown_cells: Vec::new(),
+            recorder: None,
+            recording_error: None,
+            out,
         };
         framebuffer.clear_terminal_and_fill_with_initial_color(initial_color);
         framebuffer
     }
 
+    /// Get the sink the frames are written to
+    pub fn get_writer(&self) -> &W {
+        &self.out
+    }
+
+    /// Get the sink the frames are written to as mutable
+    pub fn get_writer_mut(&mut self) -> &mut W {
+        &mut self.out
+    }
+
+    /// Consumes the framebuffer and returns the sink
+    pub fn into_writer(self) -> W {
+        self.out
+    }
+
     pub fn update_res(&mut self, width: usize, height: usize) {
         self.front_buffer.resize(width * height, 0);
         self.back_buffer.resize(width * height, 0);
+        self.front_edges.resize(width * height, Edge::None);
+        self.back_edges.resize(width * height, Edge::None);
         self.width = width;
         self.height = height;
     }
 
+    /// Resizes the framebuffer to cover `columns` x `rows` terminal cells in the current cell mode,
+    /// clears the terminal and redraws every cell on the next frame. A running recording gets a resize event.
+    pub fn resize_terminal(&mut self, columns: usize, rows: usize) {
+        let (width, height) = self.settings.cell_mode.pixel_size(columns, rows);
+        self.update_res(width, height);
+        if let Some(recorder) = &mut self.recorder {
+            if let Err(error) = recorder.write_resize(columns, rows) {
+                self.fail_recording(error);
+            }
+        }
+        self.clear_screen = true;
+        self.encoder.reset();
+        self.shown_cells.clear();
+        self.invalidate();
+    }
+
+    /// Size of the framebuffer in pixels as `(width, height)`.
+    pub fn size(&self) -> (usize, usize) {
+        (self.width, self.height)
+    }
+
+    /// Get the current output settings
+    pub fn settings(&self) -> &OutputSettings {
+        &self.settings
+    }
+
+    /// Replace the output settings and redraw every cell on the next frame.
+    /// Changing the cell mode resizes the framebuffer to cover the same terminal area.
+    pub fn set_settings(&mut self, settings: OutputSettings) {
+        let old_mode = self.settings.cell_mode;
+        let new_mode = settings.cell_mode;
+        self.settings = settings;
+
+        if old_mode != new_mode {
+            let (cell_width, cell_height) = old_mode.cell_size();
+            let columns = (self.width / cell_width) * old_mode.columns_per_cell();
+            let rows = self.height / cell_height;
+            let (width, height) = new_mode.pixel_size(columns, rows);
+            self.update_res(width, height);
+            self.clear_screen = true;
+            self.encoder.reset();
+            self.shown_cells.clear();
+        }
+        self.invalidate();
+    }
+
+    /// Get how frames are handed to the terminal
+    pub fn frame_sync(&self) -> FrameSync {
+        self.frame_sync
+    }
+
+    /// Set how frames are handed to the terminal, see `TerminalCapabilities::frame_sync` for the best one
+    pub fn set_frame_sync(&mut self, frame_sync: FrameSync) {
+        self.frame_sync = frame_sync;
+    }
+
+    /// Number of cells the last `draw_frame` wrote to the terminal
+    pub fn cells_written(&self) -> usize {
+        self.cells_written
+    }
+
+    /// Forces every cell to be redrawn on the next `draw_frame`.
+    pub fn invalidate(&mut self) {
+        for pixel in &mut self.front_buffer {
+            *pixel = INVALID_PIXEL;
+        }
+        for glyph in &mut self.glyphs {
+            *glyph = CellGlyph::default();
+        }
+    }
+
     pub fn clear(&mut self) {
         for pixel in &mut self.back_buffer {
             *pixel = 0;
         }
+        for edge in &mut self.back_edges {
+            *edge = Edge::None;
+        }
     }
 
-    pub fn draw_frame(&mut self) {
-        let characters = vec!["@", "#", "S", "%", "?", "*", "+", ";", ":", ",", ".", "\u{a0}"];
-        for y in 0..self.height {
-            for x in 0..self.width {
-                let front_pixel = self.get_pixel(x, y);
-                let back_pixel = self.back_buffer[y * self.width + x];
-                if front_pixel != back_pixel {
-                    let r = (back_pixel >> 16) & 0xff;
-                    let g = (back_pixel >> 8) & 0xff;
-                    let b = back_pixel & 0xff;
-
-                    let (h, s, l) = rgb_to_hsl(
-                        (r as f32) / 255.0,
-                        (g as f32) / 255.0,
-                        (b as f32) / 255.0
-                    );
-
-                    let character_index = ((1.0 - l) * ((characters.len() - 1) as f32)) as usize;
-                    let character = characters[character_index];
-
-                    let (fr, fg, fb) = hsl_to_rgb(h, s, 0.5);
-
-                    write!(
-                        self.out,
-                        "\x1B[{};{}H\x1b[48;2;{};{};{}m\x1b[38;2;{};{};{}m{}{}",
-                        y + 1,
-                        x * 2 + 1,
-                        r,
-                        g,
-                        b,
-                        fr,
-                        fg,
-                        fb,
-                        character,
-                        character
-                    ).unwrap();
-                }
+    /// Writes the cells that changed since the last frame to the terminal and swaps the buffers.
+    /// Fails when the output can not be written, e.g. because the terminal was closed.
+    pub fn draw_frame(&mut self) -> std::io::Result<()> {
+        palette::dither_buffer(
+            &mut self.back_buffer,
+            self.width,
+            self.height,
+            self.settings.color_depth,
+            self.settings.dither
+        );
+
+        let cell_mode = self.settings.cell_mode;
+        let (cell_width, cell_height) = cell_mode.cell_size();
+        self.encoder.coalesce = self.settings.coalesce;
+        self.encoder.color_depth = self.settings.color_depth;
+        self.encoder.line_width = (self.width / cell_width) * cell_mode.columns_per_cell();
+
+        let (columns, rows) = (self.width / cell_width, self.height / cell_height);
+        if self.glyphs.len() != columns * rows {
+            self.glyphs = vec![CellGlyph::default(); columns * rows];
+        }
+        if self.shown_cells.len() != columns * rows {
+            self.shown_cells = vec![BLANK_CELL; columns * rows];
+        }
+
+        if self.frame_sync == FrameSync::Synchronized {
+            self.pending.extend_from_slice(BEGIN_SYNCHRONIZED_UPDATE);
+        }
+        if self.clear_screen {
+            self.pending.extend_from_slice(b"\x1b[0m\x1B[2J");
+            self.clear_screen = false;
+        }
+
+        let bands: Vec<Range<usize>> = (0..rows)
+            .step_by(BAND_ROWS)
+            .map(|start| start..(start + BAND_ROWS).min(rows))
+            .collect();
+        let view = self.view();
+        let encoded: Vec<EncodedBand> = if self.settings.parallel {
+            // every band starts out not knowing the cursor and colors, its first cell is encoded again below
+            bands
+                .par_iter()
+                .map(|rows| {
+                    let mut encoder = self.encoder.clone();
+                    encoder.reset();
+                    view.encode_band(rows.clone(), encoder)
+                })
+                .collect()
+        } else {
+            let mut encoder = self.encoder.clone();
+            bands
+                .iter()
+                .map(|rows| {
+                    let band = view.encode_band(rows.clone(), encoder.clone());
+                    encoder = band.encoder.clone();
+                    band
+                })
+                .collect()
+        };
+
+        self.cells_written = 0;
+        for (rows, band) in bands.iter().zip(encoded) {
+            let mut bytes = band.bytes.as_slice();
+            if let (true, Some((row, column, cell))) = (self.settings.parallel, band.first_cell) {
+                // knowing where the previous band left the cursor and colors, the first cell may need less
+                self.encoder
+                    .write_cell(&mut self.pending, row, column, &cell, cell_mode.columns_per_cell())
+                    .unwrap();
+                bytes = &bytes[band.first_len..];
+            }
+            self.pending.extend_from_slice(bytes);
+            if band.first_cell.is_some() {
+                self.encoder = band.encoder;
+            }
+            self.glyphs[rows.start * columns..rows.end * columns].copy_from_slice(&band.glyphs);
+            self.shown_cells[rows.start * columns..rows.end * columns].copy_from_slice(&band.cells);
+            for (cell_x, cell_y) in band.kept {
+                self.keep_front_cell(cell_x, cell_y);
+            }
+            self.cells_written += band.cells_written;
+            if self.frame_sync == FrameSync::Streamed {
+                self.flush_output()?;
             }
         }
-        write!(self.out, "\x1B[{};{}H\x1b[0m", self.height + 1, 1).unwrap();
-        self.out.flush().unwrap();
+        self.encoder.finish(&mut self.pending, self.height / cell_height + 1).unwrap();
+        if self.frame_sync == FrameSync::Synchronized {
+            self.pending.extend_from_slice(END_SYNCHRONIZED_UPDATE);
+        }
+        self.flush_output()?;
         self.swap_buffers();
+        Ok(())
+    }
+
+    /// Copies the pixels and edges of the cell at `cell_x`, `cell_y` from the front to the back buffer
+    fn keep_front_cell(&mut self, cell_x: usize, cell_y: usize) {
+        let (cell_width, cell_height) = self.settings.cell_mode.cell_size();
+        for y in cell_y * cell_height..(cell_y + 1) * cell_height {
+            let row = y * self.width + cell_x * cell_width..y * self.width + (cell_x + 1) * cell_width;
+            self.back_buffer[row.clone()].copy_from_slice(&self.front_buffer[row.clone()]);
+            self.back_edges[row.clone()].copy_from_slice(&self.front_edges[row]);
+        }
+    }
+
+    /// Borrows what converting pixels to cells needs, so it can be shared between threads
+    fn view(&self) -> FrameView<'_> {
+        FrameView {
+            width: self.width,
+            settings: &self.settings,
+            front_buffer: &self.front_buffer,
+            back_buffer: &self.back_buffer,
+            front_edges: &self.front_edges,
+            back_edges: &self.back_edges,
+            glyphs: &self.glyphs,
+            shown_cells: &self.shown_cells,
+        }
+    }
+}
+
+/// Read-only view of a `TerminalFrameBuffer` with everything needed to turn its pixels into cells
+struct FrameView<'a> {
+    width: usize,
+    settings: &'a OutputSettings,
+    front_buffer: &'a [u32],
+    back_buffer: &'a [u32],
+    front_edges: &'a [Edge],
+    back_edges: &'a [Edge],
+    glyphs: &'a [CellGlyph],
+    shown_cells: &'a [Cell],
+}
+
+/// Output of a band of cell rows and the changes to apply to the framebuffer for it
+struct EncodedBand {
+    bytes: Vec<u8>,
+    /// Glyph history of the cells in the band
+    glyphs: Vec<CellGlyph>,
+    /// Cells the terminal shows in the band once the bytes are written
+    cells: Vec<Cell>,
+    /// Cells whose front pixels are kept, see `OutputSettings::color_tolerance`
+    kept: Vec<(usize, usize)>,
+    cells_written: usize,
+    /// Row and column (1-based) of the first cell written and the cell, with the length of its bytes
+    first_cell: Option<(usize, usize, Cell)>,
+    first_len: usize,
+    /// State of the terminal after the bytes
+    encoder: AnsiEncoder,
+}
+
+impl FrameView<'_> {
+    /// Converts the changed cells of the cell rows in `rows` to escape sequences, continuing from `encoder`
+    fn encode_band(&self, rows: Range<usize>, encoder: AnsiEncoder) -> EncodedBand {
+        let cell_mode = self.settings.cell_mode;
+        let columns = self.width / cell_mode.cell_size().0;
+        let hysteresis = self.settings.glyph_hysteresis.for_mode(cell_mode);
+        let mut band = EncodedBand {
+            bytes: Vec::new(),
+            glyphs: self.glyphs[rows.start * columns..rows.end * columns].to_vec(),
+            cells: self.shown_cells[rows.start * columns..rows.end * columns].to_vec(),
+            kept: Vec::new(),
+            cells_written: 0,
+            first_cell: None,
+            first_len: 0,
+            encoder,
+        };
+
+        for cell_y in rows.clone() {
+            for cell_x in 0..columns {
+                let index = (cell_y - rows.start) * columns + cell_x;
+                let changed = self.cell_changed(cell_x, cell_y);
+                // a glyph waiting for `Hysteresis::frames` has to be counted even when the pixels stay the same
+                let waiting = hysteresis.frames > 0 && band.glyphs[index].candidate.is_some();
+                if !changed && !waiting {
+                    continue;
+                }
+                let mut cell = self.mode_cell(self.back_buffer, cell_x, cell_y);
+                let (glyph, history) = self.steady_glyph(cell_x, cell_y, cell.glyph);
+                band.glyphs[index] = history;
+                cell.glyph = glyph;
+                let cell = self.edge_cell(self.back_edges, cell_x, cell_y, cell);
+                if !changed && cell == band.cells[index] {
+                    // still waiting, the terminal already shows the held glyph
+                    continue;
+                }
+                if self.settings.color_tolerance > 0.0 && self.cell_looks_the_same(cell_x, cell_y, &cell) {
+                    // keep the shown pixels, so small changes cannot add up unnoticed over several frames
+                    band.kept.push((cell_x, cell_y));
+                    continue;
+                }
+                band.cells_written += 1;
+                band.cells[index] = cell;
+                let (row, column) = (cell_y + 1, cell_x * cell_mode.columns_per_cell() + 1);
+                band.encoder
+                    .write_cell(&mut band.bytes, row, column, &cell, cell_mode.columns_per_cell())
+                    .unwrap();
+                if band.first_cell.is_none() {
+                    band.first_cell = Some((row, column, cell));
+                    band.first_len = band.bytes.len();
+                }
+            }
+        }
+        band
+    }
+
+    fn cell_changed(&self, cell_x: usize, cell_y: usize) -> bool {
+        let (cell_width, cell_height) = self.settings.cell_mode.cell_size();
+        for y in cell_y * cell_height..(cell_y + 1) * cell_height {
+            for x in cell_x * cell_width..(cell_x + 1) * cell_width {
+                if self.get_pixel(x, y) != self.back_buffer[y * self.width + x] {
+                    return true;
+                }
+                if self.settings.edges.is_some() && self.front_edges[y * self.width + x] != self.back_edges[y * self.width + x] {
+                    return true;
+                }
+            }
+        }
+        false
+    }
+
+    /// Checks if `cell` has the glyph of what the terminal shows at `cell_x`, `cell_y` and colors within `OutputSettings::color_tolerance`
+    fn cell_looks_the_same(&self, cell_x: usize, cell_y: usize, cell: &Cell) -> bool {
+        let (cell_width, cell_height) = self.settings.cell_mode.cell_size();
+        for y in cell_y * cell_height..(cell_y + 1) * cell_height {
+            for x in cell_x * cell_width..(cell_x + 1) * cell_width {
+                if self.get_pixel(x, y) == INVALID_PIXEL {
+                    return false;
+                }
+            }
+        }
+
+        let mut shown = self.mode_cell(self.front_buffer, cell_x, cell_y);
+        if let Some(glyph) = self.glyphs[cell_y * (self.width / cell_width) + cell_x].shown {
+            shown.glyph = glyph;
+        }
+        let shown = self.edge_cell(self.front_edges, cell_x, cell_y, shown);
+        let close = |a: Option<Color>, b: Option<Color>| match (a, b) {
+            (Some(a), Some(b)) => palette::perceptual_distance(a, b) <= self.settings.color_tolerance,
+            (a, b) => a == b,
+        };
+        shown.glyph == cell.glyph
+            && close(shown.foreground, cell.foreground)
+            && close(shown.background, cell.background)
+    }
+
+    /// Converts the pixels of the cell at `x`, `y` to a glyph and colors as the cell mode does, without edges
+    fn mode_cell(&self, pixels: &[u32], x: usize, y: usize) -> Cell {
+        match self.settings.cell_mode {
+            CellMode::Ascii => self.ascii_cell(pixels, x, y),
+            CellMode::HalfBlock => self.half_block_cell(pixels, x, y),
+            CellMode::Braille => self.braille_cell(pixels, x, y),
+            CellMode::Shape => self.shape_cell(pixels, x, y),
+        }
+    }
+
+    /// Replaces `cell` with a line glyph when the edge pass is on and an edge runs through it
+    fn edge_cell(&self, edges: &[Edge], x: usize, y: usize, cell: Cell) -> Cell {
+        match self.settings.edges {
+            Some(settings) => self.line_cell(edges, x, y, cell, &settings),
+            None => cell,
+        }
+    }
+
+    /// Replaces `cell` with a line glyph when an edge runs through it, following the orientation most of its pixels have
+    fn line_cell(&self, edges: &[Edge], x: usize, y: usize, cell: Cell, settings: &EdgeSettings) -> Cell {
+        let (cell_width, cell_height) = self.settings.cell_mode.cell_size();
+        let mut counts = [0usize; 4];
+        for pixel_y in y * cell_height..(y + 1) * cell_height {
+            for pixel_x in x * cell_width..(x + 1) * cell_width {
+                match edges[pixel_y * self.width + pixel_x] {
+                    Edge::None => {}
+                    Edge::Horizontal => counts[0] += 1,
+                    Edge::Vertical => counts[1] += 1,
+                    Edge::Rising => counts[2] += 1,
+                    Edge::Falling => counts[3] += 1,
+                }
+            }
+        }
+
+        let (index, count) = counts.iter().enumerate().max_by_key(|(_, count)| **count).unwrap();
+        if *count == 0 {
+            return cell;
+        }
+        let edge = [Edge::Horizontal, Edge::Vertical, Edge::Rising, Edge::Falling][index];
+        Cell {
+            glyph: edge.glyph().unwrap(),
+            foreground: Some(settings.color),
+            background: cell.background.or(cell.foreground),
+        }
+    }
+
+    /// Picks between `glyph`, freshly chosen for the cell at `cell_x`, `cell_y`, and the glyph the cell shows
+    /// as `OutputSettings::glyph_hysteresis` says. Returns the glyph to draw and the new glyph history of the cell.
+    fn steady_glyph(&self, cell_x: usize, cell_y: usize, glyph: char) -> (char, CellGlyph) {
+        let hysteresis = self.settings.glyph_hysteresis.for_mode(self.settings.cell_mode);
+        let mut state = self.glyphs[cell_y * (self.width / self.settings.cell_mode.cell_size().0) + cell_x];
+        let shown = match state.shown {
+            Some(shown) if shown != glyph => shown,
+            _ => return (glyph, CellGlyph { shown: Some(glyph), candidate: None, frames: 0 }),
+        };
+
+        let past_margin = hysteresis.margin > 0.0 && !self.within_margin(cell_x, cell_y, shown, hysteresis.margin);
+        // without a frame count there is nothing to wait for, the margin alone decides
+        if hysteresis.frames == 0 {
+            state.candidate = None;
+        } else if state.candidate == Some(glyph) {
+            state.frames += 1;
+        } else {
+            state.candidate = Some(glyph);
+            state.frames = 1;
+        }
+        let persisted = hysteresis.frames > 0 && state.frames >= hysteresis.frames;
+
+        if past_margin || persisted || (hysteresis.margin <= 0.0 && hysteresis.frames == 0) {
+            (glyph, CellGlyph { shown: Some(glyph), candidate: None, frames: 0 })
+        } else {
+            (shown, state)
+        }
+    }
+
+    /// Checks if the luminance of the cell at `cell_x`, `cell_y` is within `margin` of still picking `shown`
+    fn within_margin(&self, cell_x: usize, cell_y: usize, shown: char, margin: f32) -> bool {
+        let pixels = self.back_buffer;
+        match self.settings.cell_mode {
+            CellMode::Ascii => self.ramp_within_margin(Color::from(pixels[cell_y * self.width + cell_x]), shown, margin),
+            CellMode::Shape if self.settings.glyph_table.is_none() => {
+                let colors = (0..4 * 8).map(|i| Color::from(pixels[(cell_y * 8 + i / 4) * self.width + cell_x * 4 + i % 4]));
+                self.ramp_within_margin(average(colors), shown, margin)
+            }
+            CellMode::Braille => {
+                let shown = u32::from(shown).wrapping_sub(0x2800);
+                BRAILLE_DOTS.iter().enumerate().all(|(dot_y, dots)| {
+                    dots.iter().enumerate().all(|(dot_x, dot)| {
+                        let color = Color::from(pixels[(cell_y * 4 + dot_y) * self.width + cell_x * 2 + dot_x]);
+                        let threshold = match self.settings.braille_style {
+                            BrailleStyle::Threshold(threshold) => threshold,
+                            BrailleStyle::Ordered => BRAILLE_BAYER[dot_y][dot_x],
+                        };
+                        let luminance = self.luminance(color);
+                        (luminance > threshold) == (shown & dot != 0) || (luminance - threshold).abs() < margin
+                    })
+                })
+            }
+            CellMode::HalfBlock | CellMode::Shape => false,
+        }
+    }
+
+    /// Checks if a luminance within `margin` of the one of `color` picks `shown` from the glyph ramp
+    fn ramp_within_margin(&self, color: Color, shown: char, margin: f32) -> bool {
+        let luminance = self.luminance(color);
+        let ramp = &self.settings.glyph_ramp;
+        (ramp.index(luminance - margin)..=ramp.index(luminance + margin)).any(|index| self.ramp_glyph(index) == shown)
+    }
+
+    /// Glyph at `index` of the glyph ramp that the glyph set is able to print
+    fn ramp_glyph(&self, index: usize) -> char {
+        let glyph = self.settings.glyph_ramp.glyphs()[index];
+        if self.settings.glyph_set == GlyphSet::Ascii && !glyph.is_ascii() {
+            ' '
+        } else {
+            glyph
+        }
+    }
+
+    /// Luminance of `color` as configured in the settings, with gamma applied
+    fn luminance(&self, color: Color) -> f32 {
+        self.settings.luminance.luminance(color).powf(self.settings.gamma)
     }
 
+    fn ascii_cell(&self, pixels: &[u32], x: usize, y: usize) -> Cell {
+        self.ramp_cell(Color::from(pixels[y * self.width + x]))
+    }
+
+    /// Glyph from the glyph ramp in a saturated version of `color` on top of `color`
+    fn ramp_cell(&self, color: Color) -> Cell {
+        let Color { r, g, b } = color;
+
+        let (h, s, _l) = rgb_to_hsl(
+            (r as f32) / 255.0,
+            (g as f32) / 255.0,
+            (b as f32) / 255.0
+        );
+
+        let character = self.ramp_glyph(self.settings.glyph_ramp.index(self.luminance(color)));
+
+        let (fr, fg, fb) = hsl_to_rgb(h, s, 0.5);
+
+        Cell {
+            glyph: character,
+            foreground: Some(Color { r: fr, g: fg, b: fb }),
+            background: Some(Color { r, g, b }),
+        }
+    }
+
+    fn half_block_cell(&self, pixels: &[u32], x: usize, y: usize) -> Cell {
+        let top = Color::from(pixels[(y * 2) * self.width + x]);
+        let bottom = Color::from(pixels[(y * 2 + 1) * self.width + x]);
+
+        Cell {
+            glyph: '\u{2580}',
+            foreground: Some(top),
+            background: Some(bottom),
+        }
+    }
+
+    fn braille_cell(&self, pixels: &[u32], x: usize, y: usize) -> Cell {
+        let mut pattern = 0;
+        let mut colors = Vec::with_capacity(8);
+        let mut raised = Vec::with_capacity(8);
+
+        for (dot_y, dots) in BRAILLE_DOTS.iter().enumerate() {
+            for (dot_x, dot) in dots.iter().enumerate() {
+                let color = Color::from(pixels[(y * 4 + dot_y) * self.width + x * 2 + dot_x]);
+                let threshold = match self.settings.braille_style {
+                    BrailleStyle::Threshold(threshold) => threshold,
+                    BrailleStyle::Ordered => BRAILLE_BAYER[dot_y][dot_x],
+                };
+
+                colors.push(color);
+                if self.luminance(color) > threshold {
+                    pattern |= dot;
+                    raised.push(color);
+                }
+            }
+        }
+
+        // the foreground is the average of the raised dots, or of the whole cell when none are raised
+        let foreground = if raised.is_empty() { average(colors.into_iter()) } else { average(raised.into_iter()) };
+
+        Cell {
+            glyph: char::from_u32(0x2800 + pattern).unwrap(),
+            foreground: Some(foreground),
+            background: None,
+        }
+    }
+
+    fn shape_cell(&self, pixels: &[u32], x: usize, y: usize) -> Cell {
+        const PIXELS: usize = 4 * 8;
+        let mut colors = [Color { r: 0, g: 0, b: 0 }; PIXELS];
+        let mut luminances = [0.0f32; PIXELS];
+        for (i, (color, luminance)) in colors.iter_mut().zip(luminances.iter_mut()).enumerate() {
+            *color = Color::from(pixels[(y * 8 + i / 4) * self.width + x * 4 + i % 4]);
+            *luminance = self.luminance(*color);
+        }
+
+        let table = match &self.settings.glyph_table {
+            Some(table) => table,
+            None => return self.ramp_cell(average(colors.iter().copied())),
+        };
+
+        let min = luminances.iter().copied().fold(f32::MAX, f32::min);
+        let max = luminances.iter().copied().fold(f32::MIN, f32::max);
+        if max - min < 1.0 / 32.0 {
+            let color = average(colors.iter().copied());
+            return Cell { glyph: ' ', foreground: Some(color), background: Some(color) };
+        }
+
+        let threshold = (min + max) / 2.0;
+        let bright = average(colors.iter().zip(&luminances).filter(|(_, l)| **l > threshold).map(|(c, _)| *c));
+        let dark = average(colors.iter().zip(&luminances).filter(|(_, l)| **l <= threshold).map(|(c, _)| *c));
+
+        // the glyph covers the bright pixels, or the dark ones when that matches better
+        let mut coverage = [0.0f32; PIXELS];
+        for (coverage, luminance) in coverage.iter_mut().zip(&luminances) {
+            *coverage = (luminance - min) / (max - min);
+        }
+        let normal = table.closest(&coverage);
+        for coverage in coverage.iter_mut() {
+            *coverage = 1.0 - *coverage;
+        }
+        let inverted = table.closest(&coverage);
+
+        match (normal, inverted) {
+            (Some((glyph, error)), Some((_, inverted_error))) if error <= inverted_error => {
+                Cell { glyph, foreground: Some(bright), background: Some(dark) }
+            }
+            (_, Some((glyph, _))) => Cell { glyph, foreground: Some(dark), background: Some(bright) },
+            _ => Cell { glyph: ' ', foreground: Some(dark), background: Some(dark) },
+        }
+    }
+
+    fn get_pixel(&self, x: usize, y: usize) -> u32 {
+        self.front_buffer[y * self.width + x]
+    }
+}
+
+impl<W: Write> TerminalFrameBuffer<W> {
     pub fn set_pixel(&mut self, x: usize, y: usize, color: Color) {
-        let color = (u32::from(color.r) << 16) | (u32::from(color.g) << 8) | u32::from(color.b);
+        let color = u32::from(color);
         if x < self.width && y < self.height {
             self.back_buffer[y * self.width + x] = color;
         }
     }
 
-    fn get_pixel(&self, x: usize, y: usize) -> u32 {
-        self.front_buffer[y * self.width + x]
+    /// Cells the terminal shows after the last drawn frame, row by row
+    pub fn front_cells(&self) -> Vec<Cell> {
+        self.shown_cells.clone()
+    }
+
+    /// Exports the last drawn frame as a self-contained HTML page, in the current cell mode and color depth
+    pub fn to_html(&self) -> String {
+        let cell_mode = self.settings.cell_mode;
+        export::html(
+            &self.front_cells(),
+            self.width / cell_mode.cell_size().0,
+            cell_mode.columns_per_cell(),
+            self.settings.color_depth
+        )
+    }
+
+    /// Exports the last drawn frame as an SVG document, in the current cell mode and color depth
+    pub fn to_svg(&self) -> String {
+        let cell_mode = self.settings.cell_mode;
+        export::svg(
+            &self.front_cells(),
+            self.width / cell_mode.cell_size().0,
+            cell_mode.columns_per_cell(),
+            self.settings.color_depth
+        )
+    }
+
+    /// Starts writing everything drawn to `recorder`, beginning with a full frame on the next `draw_frame`.
+    /// A recording that is already running is stopped and returned.
+    pub fn start_recording(&mut self, mut recorder: AsciicastRecorder) -> std::io::Result<Option<AsciicastRecorder>> {
+        recorder.write_output(b"\x1b[0m\x1B[2J")?;
+        self.encoder.reset();
+        self.invalidate();
+        Ok(self.recorder.replace(recorder))
+    }
+
+    /// Stops the recording and returns it, call `AsciicastRecorder::finish` to flush it
+    pub fn stop_recording(&mut self) -> Option<AsciicastRecorder> {
+        self.recorder.take()
+    }
+
+    /// Checks if a recording is running
+    pub fn is_recording(&self) -> bool {
+        self.recorder.is_some()
+    }
+
+    /// Takes the error a recording was stopped with because it could not be written
+    pub fn take_recording_error(&mut self) -> Option<std::io::Error> {
+        self.recording_error.take()
+    }
+
+    /// Stops a recording that could not be written, drawing goes on without it
+    fn fail_recording(&mut self, error: std::io::Error) {
+        self.recorder = None;
+        self.recording_error = Some(error);
+    }
+
+    /// Resets colors and clears the screen, so no colored cells are left behind.
+    /// The cursor and the shell's screen come back when the `TerminalSession` ends.
+    pub fn restore_terminal(&mut self) -> std::io::Result<()> {
+        write!(self.pending, "\x1b[0m\x1B[2J\x1B[1;1H")?;
+        self.encoder.reset();
+        self.flush_output()
+    }
+
+    /// Writes the pending output to the sink and the recording
+    fn flush_output(&mut self) -> std::io::Result<()> {
+        self.out.write_all(&self.pending)?;
+        self.out.flush()?;
+        if let Some(recorder) = &mut self.recorder {
+            // flushed with every frame, so a killed game leaves a playable recording
+            if let Err(error) = recorder.write_output(&self.pending).and_then(|_| recorder.flush()) {
+                self.fail_recording(error);
+            }
+        }
+        self.pending.clear();
+        Ok(())
+    }
+
+    /// Marks the edge running through the pixel at `x`, `y`, drawn when `OutputSettings::edges` is set
+    pub fn set_edge(&mut self, x: usize, y: usize, edge: Edge) {
+        if x < self.width && y < self.height {
+            self.back_edges[y * self.width + x] = edge;
+        }
     }
 
     fn swap_buffers(&mut self) {
         std::mem::swap(&mut self.front_buffer, &mut self.back_buffer);
+        std::mem::swap(&mut self.front_edges, &mut self.back_edges);
     }
 
     pub fn clear_terminal_and_fill_with_initial_color(&mut self, initial_color: Color) {
-        write!(self.out, "\x1B[2J\x1B[1;1H").unwrap();
-        for _y in 0..self.height {
-            for _x in 0..self.width {
-                write!(
-                    self.out,
-                    "\x1b[48;2;{};{};{}m  ",
-                    initial_color.r,
-                    initial_color.g,
-                    initial_color.b
-                ).unwrap();
-            }
-            writeln!(self.out, "\x1b[0m").unwrap();
+        let cell_mode = self.settings.cell_mode;
+        let (cell_width, cell_height) = cell_mode.cell_size();
+        let columns = (self.width / cell_width) * cell_mode.columns_per_cell();
+        write!(self.pending, "\x1B[2J\x1B[1;1H").unwrap();
+        let background = Cell {
+            background: Some(initial_color),
+            ..BLANK_CELL
+        };
+        self.shown_cells = vec![background; (self.width / cell_width) * (self.height / cell_height)];
+        for _y in 0..self.height / cell_height {
+            palette::write_sgr(&mut self.pending, initial_color, self.settings.color_depth, true).unwrap();
+            write!(self.pending, "{}", " ".repeat(columns)).unwrap();
+            writeln!(self.pending, "\x1b[0m").unwrap();
         }
-        self.out.flush().unwrap();
+        self.flush_output().unwrap();
+        self.encoder.reset();
+    }
+}
+
+/// Average of `colors`, black when there are none
+fn average<I: Iterator<Item = Color>>(colors: I) -> Color {
+    let mut sum = [0u32; 3];
+    let mut count = 0;
+    for color in colors {
+        sum[0] += u32::from(color.r);
+        sum[1] += u32::from(color.g);
+        sum[2] += u32::from(color.b);
+        count += 1;
+    }
+    let count = count.max(1);
+    Color {
+        r: (sum[0] / count) as u8,
+        g: (sum[1] / count) as u8,
+        b: (sum[2] / count) as u8,
     }
 }
 
@@ -176,3 +1095,429 @@ fn hsl_to_rgb(h: f32, s: f32, l: f32) -> (u8, u8, u8) {
 
     (r, g, b)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Framebuffer covering `columns` x `rows` of a terminal that is captured into a `Vec<u8>`
+    fn capture(columns: usize, rows: usize, settings: OutputSettings) -> TerminalFrameBuffer<Vec<u8>> {
+        let (width, height) = CellMode::default().pixel_size(columns, rows);
+        let mut framebuffer =
+            TerminalFrameBuffer::with_writer(width, height, Color { r: 0, g: 0, b: 0 }, Vec::new());
+        framebuffer.set_settings(settings);
+        framebuffer.clear_screen = false;
+        framebuffer.get_writer_mut().clear();
+        framebuffer
+    }
+
+    fn fill(framebuffer: &mut TerminalFrameBuffer<Vec<u8>>, color: Color) {
+        let (width, height) = framebuffer.size();
+        framebuffer.clear();
+        for y in 0..height {
+            for x in 0..width {
+                framebuffer.set_pixel(x, y, color);
+            }
+        }
+    }
+
+    #[test]
+    fn frames_are_written_to_the_sink() {
+        let mut framebuffer = capture(2, 1, OutputSettings {
+            cell_mode: CellMode::HalfBlock,
+            coalesce: false,
+            ..Default::default()
+        });
+        assert_eq!(framebuffer.size(), (2, 2));
+
+        fill(&mut framebuffer, Color { r: 0, g: 0, b: 0 });
+        framebuffer.set_pixel(1, 0, Color { r: 255, g: 0, b: 0 });
+        framebuffer.set_pixel(1, 1, Color { r: 0, g: 0, b: 255 });
+        framebuffer.draw_frame().unwrap();
+
+        let frame = String::from_utf8(framebuffer.into_writer()).unwrap();
+        assert_eq!(
+            frame,
+            "\x1B[1;1H\x1b[48;2;0;0;0m\x1b[38;2;0;0;0m\u{2580}\
+             \x1B[1;2H\x1b[48;2;0;0;255m\x1b[38;2;255;0;0m\u{2580}\
+             \x1B[2;1H\x1b[0m"
+        );
+    }
+
+    #[test]
+    fn synchronized_frames_are_wrapped_in_mode_2026() {
+        let mut framebuffer = capture(2, 1, OutputSettings { cell_mode: CellMode::HalfBlock, ..Default::default() });
+        framebuffer.set_frame_sync(FrameSync::Synchronized);
+
+        fill(&mut framebuffer, Color { r: 255, g: 0, b: 0 });
+        framebuffer.draw_frame().unwrap();
+        let frame = String::from_utf8(framebuffer.get_writer().clone()).unwrap();
+        assert!(frame.starts_with("\x1b[?2026h\x1B[1;1H"));
+        assert!(frame.ends_with("\x1b[?2026l"));
+
+        // clearing the resized terminal is part of the update
+        framebuffer.get_writer_mut().clear();
+        framebuffer.resize_terminal(4, 1);
+        fill(&mut framebuffer, Color { r: 255, g: 0, b: 0 });
+        framebuffer.draw_frame().unwrap();
+        let frame = String::from_utf8(framebuffer.get_writer().clone()).unwrap();
+        assert!(frame.starts_with("\x1b[?2026h\x1b[0m\x1B[2J\x1B[1;1H"));
+        assert!(frame.ends_with("\x1b[?2026l"));
+    }
+
+    /// Sink keeping what was written between flushes apart
+    #[derive(Default)]
+    struct Flushes {
+        flushed: Vec<Vec<u8>>,
+        buffer: Vec<u8>,
+    }
+
+    impl Write for Flushes {
+        fn write(&mut self, data: &[u8]) -> std::io::Result<usize> {
+            self.buffer.write(data)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            if !self.buffer.is_empty() {
+                self.flushed.push(std::mem::take(&mut self.buffer));
+            }
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn only_streamed_frames_are_split_up() {
+        let written = |frame_sync: FrameSync| {
+            let (width, height) = CellMode::default().pixel_size(10, BAND_ROWS * 3);
+            let mut framebuffer =
+                TerminalFrameBuffer::with_writer(width, height, Color { r: 0, g: 0, b: 0 }, Flushes::default());
+            framebuffer.set_frame_sync(frame_sync);
+            framebuffer.get_writer_mut().flushed.clear();
+            for y in 0..height {
+                for x in 0..width {
+                    framebuffer.set_pixel(x, y, Color { r: (x * 20) as u8, g: (y * 10) as u8, b: 0 });
+                }
+            }
+            framebuffer.draw_frame().unwrap();
+            framebuffer.into_writer().flushed
+        };
+
+        let single = written(FrameSync::SingleWrite);
+        let streamed = written(FrameSync::Streamed);
+        assert_eq!(single.len(), 1);
+        assert_eq!(streamed.len(), 4);
+        assert_eq!(streamed.concat(), single.concat());
+    }
+
+    #[test]
+    fn changes_within_the_color_tolerance_are_skipped() {
+        let mut framebuffer = capture(2, 1, OutputSettings {
+            cell_mode: CellMode::HalfBlock,
+            color_depth: ColorDepth::TrueColor,
+            color_tolerance: 10.0,
+            ..Default::default()
+        });
+
+        fill(&mut framebuffer, Color { r: 100, g: 100, b: 100 });
+        framebuffer.draw_frame().unwrap();
+        assert_eq!(framebuffer.cells_written(), 2);
+
+        fill(&mut framebuffer, Color { r: 102, g: 100, b: 100 });
+        framebuffer.draw_frame().unwrap();
+        assert_eq!(framebuffer.cells_written(), 0);
+
+        // the change adds up against what is shown, not against the previous frame
+        fill(&mut framebuffer, Color { r: 104, g: 100, b: 100 });
+        framebuffer.draw_frame().unwrap();
+        assert_eq!(framebuffer.cells_written(), 0);
+        fill(&mut framebuffer, Color { r: 108, g: 100, b: 100 });
+        framebuffer.draw_frame().unwrap();
+        assert_eq!(framebuffer.cells_written(), 2);
+    }
+
+    #[test]
+    fn glyph_hysteresis_holds_glyphs_near_a_boundary() {
+        // grey 139 and 140 are on both sides of the boundary between '*' and '?' of the default ramp
+        let grey = |value| Color { r: value, g: value, b: value };
+        let frame = |framebuffer: &mut TerminalFrameBuffer<Vec<u8>>, value| {
+            framebuffer.get_writer_mut().clear();
+            fill(framebuffer, grey(value));
+            framebuffer.draw_frame().unwrap();
+            String::from_utf8(framebuffer.get_writer().clone()).unwrap()
+        };
+
+        let mut margin = capture(2, 1, OutputSettings {
+            color_depth: ColorDepth::TrueColor,
+            glyph_hysteresis: GlyphHysteresis { ascii: Hysteresis { margin: 0.05, frames: 0 }, ..Default::default() },
+            ..Default::default()
+        });
+        assert!(frame(&mut margin, 139).contains("**"));
+        assert!(frame(&mut margin, 140).contains("**"));
+        // exports show the held glyph as well
+        assert_eq!(margin.front_cells()[0].glyph, '*');
+        // nothing to wait for, so an unchanged cell is not written again
+        frame(&mut margin, 140);
+        assert_eq!(margin.cells_written(), 0);
+        assert!(frame(&mut margin, 139).contains("**"));
+        assert!(frame(&mut margin, 160).contains("??"));
+
+        let mut frames = capture(2, 1, OutputSettings {
+            color_depth: ColorDepth::TrueColor,
+            glyph_hysteresis: GlyphHysteresis { ascii: Hysteresis { margin: 0.0, frames: 3 }, ..Default::default() },
+            ..Default::default()
+        });
+        assert!(frame(&mut frames, 139).contains("**"));
+        assert!(frame(&mut frames, 140).contains("**"));
+        // the pixels stay the same and the held glyph is already shown
+        frame(&mut frames, 140);
+        assert_eq!(frames.cells_written(), 0);
+        // the waiting glyph has been picked three times now
+        assert!(frame(&mut frames, 140).contains("??"));
+        assert_eq!(frames.cells_written(), 1);
+    }
+
+    #[test]
+    fn glyph_changes_are_not_hidden_by_the_color_tolerance() {
+        let mut framebuffer = capture(2, 1, OutputSettings {
+            color_depth: ColorDepth::TrueColor,
+            color_tolerance: 20.0,
+            ..Default::default()
+        });
+        let grey = |value| Color { r: value, g: value, b: value };
+
+        fill(&mut framebuffer, grey(139));
+        framebuffer.draw_frame().unwrap();
+        framebuffer.get_writer_mut().clear();
+
+        // within the tolerance, but past the boundary between '*' and '?'
+        fill(&mut framebuffer, grey(141));
+        framebuffer.draw_frame().unwrap();
+        assert_eq!(framebuffer.cells_written(), 1);
+        assert!(String::from_utf8(framebuffer.get_writer().clone()).unwrap().contains("??"));
+        assert_eq!(framebuffer.front_cells()[0].glyph, '?');
+
+        // and the same glyph within the tolerance is skipped
+        framebuffer.get_writer_mut().clear();
+        fill(&mut framebuffer, grey(143));
+        framebuffer.draw_frame().unwrap();
+        assert_eq!(framebuffer.cells_written(), 0);
+    }
+
+    #[test]
+    fn parallel_output_matches_serial_output() {
+        let render = |settings: OutputSettings| {
+            let mut framebuffer = capture(80, 40, settings);
+            let (width, height) = framebuffer.size();
+            let mut seed = 1u32;
+            for _ in 0..3 {
+                framebuffer.clear();
+                for y in 0..height {
+                    for x in 0..width {
+                        // a few noisy frames, so some cells change and some stay the same
+                        seed = seed.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+                        let noise = if x < width / 2 { (seed >> 28) as u8 } else { 0 };
+                        let value = ((x * 3 + y * 5) % 256) as u8;
+                        framebuffer.set_pixel(x, y, Color { r: value, g: value.wrapping_add(noise), b: 255 - value });
+                        if (x + y) % 7 == 0 {
+                            framebuffer.set_edge(x, y, Edge::Vertical);
+                        }
+                    }
+                }
+                framebuffer.draw_frame().unwrap();
+            }
+            framebuffer.into_writer()
+        };
+
+        let hysteresis = Hysteresis { margin: 0.02, frames: 2 };
+        for cell_mode in [CellMode::Ascii, CellMode::HalfBlock, CellMode::Braille, CellMode::Shape] {
+            let settings = OutputSettings {
+                cell_mode,
+                color_tolerance: 8.0,
+                glyph_hysteresis: GlyphHysteresis { ascii: hysteresis, braille: hysteresis, shape: hysteresis },
+                edges: Some(EdgeSettings::default()),
+                ..Default::default()
+            };
+            let parallel = render(OutputSettings { parallel: true, ..settings.clone() });
+            let serial = render(OutputSettings { parallel: false, ..settings });
+            assert!(parallel.len() > 1000);
+            assert!(parallel == serial, "{:?} output differs", cell_mode);
+        }
+
+        // the colors are sent once, not again at the start of every band
+        for parallel in [false, true] {
+            let mut framebuffer = capture(10, BAND_ROWS * 3, OutputSettings {
+                cell_mode: CellMode::HalfBlock,
+                parallel,
+                ..Default::default()
+            });
+            fill(&mut framebuffer, Color { r: 10, g: 20, b: 30 });
+            framebuffer.draw_frame().unwrap();
+            let frame = String::from_utf8(framebuffer.into_writer()).unwrap();
+            assert_eq!(frame.matches("\x1b[38;2;10;20;30;48;2;10;20;30m").count(), 1, "parallel: {}", parallel);
+            assert_eq!(frame.matches('\u{2580}').count(), 10 * BAND_ROWS * 3);
+        }
+    }
+
+    #[test]
+    fn redundant_sequences_are_coalesced() {
+        let mut framebuffer = capture(6, 2, OutputSettings {
+            cell_mode: CellMode::HalfBlock,
+            ..Default::default()
+        });
+        let red = Color { r: 255, g: 0, b: 0 };
+        fill(&mut framebuffer, red);
+        framebuffer.set_pixel(4, 2, Color { r: 0, g: 0, b: 255 });
+        framebuffer.draw_frame().unwrap();
+        framebuffer.get_writer_mut().clear();
+
+        // the first, the last and the previously blue cell of the bottom row change
+        fill(&mut framebuffer, red);
+        framebuffer.set_pixel(0, 2, Color { r: 0, g: 255, b: 0 });
+        framebuffer.set_pixel(5, 3, Color { r: 0, g: 255, b: 0 });
+        framebuffer.draw_frame().unwrap();
+
+        let frame = String::from_utf8(framebuffer.into_writer()).unwrap();
+        assert_eq!(
+            frame,
+            "\x1B[2;1H\x1b[38;2;0;255;0;48;2;255;0;0m\u{2580}\
+             \x1b[3C\x1b[38;2;255;0;0m\u{2580}\
+             \x1b[48;2;0;255;0m\u{2580}\
+             \x1B[3;1H\x1b[0m"
+        );
+    }
+
+    #[test]
+    fn unchanged_cells_are_skipped() {
+        let grey = Color { r: 128, g: 128, b: 128 };
+        let mut framebuffer = capture(8, 4, OutputSettings::default());
+        fill(&mut framebuffer, grey);
+        framebuffer.draw_frame().unwrap();
+        framebuffer.get_writer_mut().clear();
+
+        fill(&mut framebuffer, grey);
+        framebuffer.draw_frame().unwrap();
+        assert_eq!(framebuffer.get_writer().as_slice(), b"\x1B[5;1H\x1b[0m");
+    }
+
+    #[test]
+    fn recordings_are_written_through_every_frame() {
+        let path = std::env::temp_dir().join(format!("ascii_render_recording_{}.cast", std::process::id()));
+        let mut framebuffer = capture(4, 2, OutputSettings::default());
+        framebuffer.start_recording(AsciicastRecorder::create(&path, 4, 2).unwrap()).unwrap();
+        fill(&mut framebuffer, Color { r: 255, g: 0, b: 0 });
+        framebuffer.draw_frame().unwrap();
+
+        // still recording, nothing was finished yet
+        let recorded = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!(framebuffer.is_recording());
+        assert_eq!(recorded.lines().count(), 3);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn failed_recordings_stop_without_stopping_the_frames() {
+        let mut framebuffer = capture(80, 24, OutputSettings::default());
+        let recorder = AsciicastRecorder::create("/dev/full", 80, 24).unwrap();
+        framebuffer.start_recording(recorder).unwrap();
+
+        // the recording is buffered, the first frames fill it up until it has to hit the full device
+        for shade in 0..8 {
+            fill(&mut framebuffer, Color { r: shade * 30, g: 0, b: 0 });
+            framebuffer.draw_frame().unwrap();
+        }
+
+        assert!(!framebuffer.is_recording());
+        assert!(framebuffer.take_recording_error().is_some());
+        assert!(framebuffer.take_recording_error().is_none());
+
+        framebuffer.get_writer_mut().clear();
+        fill(&mut framebuffer, Color { r: 255, g: 255, b: 255 });
+        framebuffer.draw_frame().unwrap();
+        assert!(!framebuffer.get_writer().is_empty());
+    }
+
+    /// A terminal that can be closed while the game is drawing to it
+    struct Terminal {
+        closed: bool,
+    }
+
+    impl Write for Terminal {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            if self.closed {
+                Err(std::io::ErrorKind::BrokenPipe.into())
+            } else {
+                Ok(buf.len())
+            }
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn frames_fail_when_the_terminal_is_gone() {
+        for frame_sync in [FrameSync::SingleWrite, FrameSync::Streamed] {
+            let (width, height) = CellMode::default().pixel_size(4, 2);
+            let mut framebuffer =
+                TerminalFrameBuffer::with_writer(width, height, Color { r: 0, g: 0, b: 0 }, Terminal { closed: false });
+            framebuffer.set_frame_sync(frame_sync);
+            framebuffer.draw_frame().unwrap();
+
+            framebuffer.get_writer_mut().closed = true;
+            framebuffer.set_pixel(0, 0, Color { r: 255, g: 0, b: 0 });
+            let error = framebuffer.draw_frame().unwrap_err();
+            assert_eq!(error.kind(), std::io::ErrorKind::BrokenPipe);
+        }
+    }
+
+    #[test]
+    fn exports_show_the_cells_on_the_terminal() {
+        let settings = OutputSettings {
+            cell_mode: CellMode::HalfBlock,
+            ..Default::default()
+        };
+        let mut framebuffer = capture(4, 2, settings.clone());
+        let blue = Color { r: 0, g: 0, b: 255 };
+        fill(&mut framebuffer, blue);
+        framebuffer.draw_frame().unwrap();
+        let cells = framebuffer.front_cells();
+        assert_eq!(cells.len(), 8);
+        assert!(cells.iter().all(|cell| cell.foreground == Some(blue) && cell.background == Some(blue)));
+        let html = framebuffer.to_html();
+
+        // nothing new was drawn since the front buffer was invalidated
+        framebuffer.set_settings(OutputSettings {
+            color_tolerance: 5.0,
+            ..settings
+        });
+        assert_eq!(framebuffer.front_cells(), cells);
+        assert_eq!(framebuffer.to_html(), html);
+
+        framebuffer.resize_terminal(2, 1);
+        framebuffer.draw_frame().unwrap();
+        assert_eq!(framebuffer.front_cells().len(), 2);
+    }
+
+    #[test]
+    fn resizing_keeps_the_cell_mode() {
+        let settings = OutputSettings {
+            cell_mode: CellMode::Braille,
+            ..Default::default()
+        };
+        let mut framebuffer = capture(4, 2, settings.clone());
+        assert_eq!(framebuffer.size(), (8, 8));
+
+        framebuffer.resize_terminal(10, 3);
+        assert_eq!(framebuffer.size(), (20, 12));
+        assert_eq!(*framebuffer.settings(), settings);
+
+        // everything is redrawn after the terminal was cleared
+        fill(&mut framebuffer, Color { r: 0, g: 0, b: 0 });
+        framebuffer.draw_frame().unwrap();
+        let frame = String::from_utf8(framebuffer.into_writer()).unwrap();
+        assert!(frame.starts_with("\x1b[0m\x1B[2J"));
+        assert_eq!(frame.matches('\u{2800}').count(), 30);
+    }
+}
@@ -1,30 +1,24 @@
 // use std::fmt::Display;
 
-use super::ascii_render::{Color, TerminalFrameBuffer};
+use super::ascii_render::{Color, OutputSettings, TerminalFrameBuffer};
 use super::camera::Camera;
-use super::object::{Object, TextureFilter};
+use super::error::EngineError;
+use super::headless::HeadlessRenderer;
+use super::object::{CpuOnly, Object, Upload};
+use super::recorder::AsciicastRecorder;
+use super::render::{self, GlRenderer, RenderSettings, Renderer, ShaderPaths};
+use super::software::SoftwareRenderer;
 // use super::matrices::{ model_matrix };
 // use super::prefab::{get_prefabs, PrefabList};
 use device_query::DeviceState;
 use glium::glutin;
-use glium::Surface;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
 use terminal_size::terminal_size;
 // use super::game_loop::game_loop;
 use super::scene::Scene;
-
-//create init type
-
-type InitType = (
-    (u32, u32),
-    TerminalFrameBuffer,
-    glutin::event_loop::EventLoop<()>,
-    glium::Display,
-    glium::Program,
-    glium::Program,
-    glium::DrawParameters<'static>,
-    glium::DrawParameters<'static>,
-    Game,
-);
+use super::signal::{self, SignalHandlers};
+use super::terminal::{TerminalCapabilities, TerminalSession};
 
 #[derive(Debug)]
 pub struct UiElems {
@@ -38,11 +32,34 @@ pub struct Game {
     current_scene: usize,
     pub delta_time: f32,
     pub assets_path: String,
+    /// How the rendered image is written to the terminal, applied before every frame.
+    /// Starts out as the best settings for the detected capabilities in the engine, as the defaults with `Game::new`,
+    /// and can be overridden by the game.
+    pub output: OutputSettings,
+    capabilities: TerminalCapabilities,
+    /// File the terminal output is recorded to, picked up before the next frame
+    recording: Option<PathBuf>,
+    /// Why the last recording stopped on its own
+    recording_error: Option<std::io::Error>,
+    /// Exit code the engine stops with, picked up before the next frame
+    exit_code: Option<i32>,
+    /// Terminal cells written by the last frame
+    cells_written: usize,
+    /// Where the cursor is moved to after the current update
+    cursor_position: Option<(f64, f64)>,
 }
 
 #[allow(dead_code)]
 impl Game {
+    /// Create a game with the default output settings, `with_capabilities` starts from the best ones for a terminal instead
     pub fn new(camera: Camera, assets_path: String) -> Game {
+        let mut game = Game::with_capabilities(camera, assets_path, TerminalCapabilities::from_env());
+        game.output = OutputSettings::default();
+        game
+    }
+
+    /// Create a game for a terminal with the given capabilities, with output settings that make the most of them
+    pub fn with_capabilities(camera: Camera, assets_path: String, capabilities: TerminalCapabilities) -> Game {
         Game {
             scenes: Vec::new(),
             ui_elems: UiElems { elems: Vec::new() },
@@ -50,9 +67,75 @@ impl Game {
             current_scene: 0,
             delta_time: 0.0,
             assets_path,
+            output: capabilities.output_settings(),
+            capabilities,
+            recording: None,
+            recording_error: None,
+            exit_code: None,
+            cells_written: 0,
+            cursor_position: None,
         }
     }
 
+    /// Get the capabilities detected for the terminal
+    pub fn get_capabilities(&self) -> &TerminalCapabilities {
+        &self.capabilities
+    }
+
+    /// Start recording the terminal output to an asciicast v2 file at `path`, from the next frame on.
+    /// A recording that is already running is stopped first.
+    pub fn start_recording<P: Into<PathBuf>>(&mut self, path: P) {
+        self.recording = Some(path.into());
+    }
+
+    /// Stop recording the terminal output
+    pub fn stop_recording(&mut self) {
+        self.recording = None;
+    }
+
+    /// Get the file the terminal output is recorded to
+    pub fn get_recording_path(&self) -> Option<&Path> {
+        self.recording.as_deref()
+    }
+
+    /// Take the error of a recording that could not be created or written.
+    /// The engine stops such a recording and keeps running, so `get_recording_path` returns `None` afterwards.
+    pub fn take_recording_error(&mut self) -> Option<std::io::Error> {
+        self.recording_error.take()
+    }
+
+    /// Stop a recording that failed with `error`
+    fn fail_recording(&mut self, error: std::io::Error) {
+        self.recording = None;
+        self.recording_error = Some(error);
+    }
+
+    /// Stop the engine before the next frame, `Engine::run` returns exit code 0
+    pub fn request_exit(&mut self) {
+        self.request_exit_with_code(0);
+    }
+
+    /// Stop the engine before the next frame, `Engine::run` returns `code`
+    pub fn request_exit_with_code(&mut self, code: i32) {
+        self.exit_code = Some(code);
+    }
+
+    /// Get the exit code the game asked to stop with
+    pub fn get_exit_request(&self) -> Option<i32> {
+        self.exit_code
+    }
+
+    /// Move the mouse cursor to `x`, `y` in pixels of the hidden window after the current update,
+    /// e.g. to keep it centered for mouse look. Does nothing without a window, see `Backend::Software`
+    pub fn set_cursor_position(&mut self, x: f64, y: f64) {
+        self.cursor_position = Some((x, y));
+    }
+
+    /// Get the number of terminal cells the last frame wrote, to tune `OutputSettings::color_tolerance`
+    pub fn get_cells_written(&self) -> usize {
+        self.cells_written
+    }
+
     /// Add a scene to the game
     pub fn add_scene(&mut self, scene: Scene) {
         self.scenes.push(scene);
@@ -111,118 +194,574 @@ impl Game {
     }
 }
 
-pub fn init(assets_path: &str) -> InitType {
-    let terminal_res = terminal_size().unwrap();
-    let terminal_res: (u32, u32) = (u32::from(terminal_res.0 .0), u32::from(terminal_res.1 .0));
-
-    let terminal_fb = TerminalFrameBuffer::new(
-        (terminal_res.0 as usize) / 2,
-        terminal_res.1 as usize,
-        Color { r: 0, g: 0, b: 0 },
-    );
-
-    let event_loop = glutin::event_loop::EventLoop::new();
-    let wb = glutin::window::WindowBuilder::new()
-        .with_visible(false)
-        .with_inner_size(glutin::dpi::LogicalSize::new(
-            terminal_res.0,
-            terminal_res.1,
-        ));
-
-    let cb = glutin::ContextBuilder::new().with_depth_buffer(24);
-    let display = glium::Display::new(wb, cb, &event_loop).unwrap();
-
-    //read vertex shader source code from file
-    // let vertex_shader_src = std::fs::read_to_string("assets/shaders/vertex_shader.glsl")
-        // .expect("Failed to read vertex shader source code from file");
-
-    let vertex_path = format!("{}/shaders/vertex_shader.glsl", assets_path);
-    let vertex_shader_src = std::fs::read_to_string(vertex_path)
-        .expect("Failed to read vertex shader source code from file");
-
-    // let fragment_shader_src = std::fs::read_to_string("assets/shaders/fragment_shader.glsl")
-        // .expect("Failed to read fragment shader source code from file");
-
-    let fragment_path = format!("{}/shaders/fragment_shader.glsl", assets_path);
-    let fragment_shader_src = std::fs::read_to_string(fragment_path)
-        .expect("Failed to read fragment shader source code from file");
-
-    let program = glium::Program::from_source(
-        &display,
-        vertex_shader_src.as_str(),
-        fragment_shader_src.as_str(),
-        None,
-    )
-    .unwrap();
-
-    let params = glium::DrawParameters {
-        depth: glium::Depth {
-            test: glium::draw_parameters::DepthTest::IfLess,
-            write: true,
-            ..Default::default()
-        },
-        blend: glium::Blend::alpha_blending(),
-        //set texture filtering to nearest
-        backface_culling: glium::draw_parameters::BackfaceCullingMode::CullClockwise,
-        ..Default::default()
-    };
+/// Game logic driven by the engine, owning whatever state the game needs between frames.
+/// Every hook does nothing by default, so only the ones the game uses have to be implemented.
+pub trait GameState {
+    /// Called once before the first frame, e.g. to load scenes and UI.
+    /// `display` is where objects are uploaded to, for the backend the engine runs with
+    fn init(&mut self, _terminal_res: (u32, u32), _game: &mut Game, _display: &dyn Upload) {}
+
+    /// Called at the fixed timestep of `EngineBuilder::timestep`, zero or more times per frame.
+    /// `device_state` is `None` when the keyboard and mouse can not be read, e.g. without an X display on Linux
+    fn fixed_update(
+        &mut self,
+        _device_state: Option<&DeviceState>,
+        _terminal_res: (u32, u32),
+        _game: &mut Game,
+        _display: &dyn Upload,
+    ) {
+    }
 
-    let ui_params = glium::DrawParameters {
-        blend: glium::Blend::alpha_blending(),
-        //set texture filtering to nearest
-        backface_culling: glium::draw_parameters::BackfaceCullingMode::CullClockwise,
-        ..Default::default()
-    };
+    /// Called once per frame before it is rendered, with the seconds since the last frame
+    fn update(&mut self, _dt: f32, _game: &mut Game, _display: &dyn Upload) {}
 
-    // let ui_vertex_shader_src = std::fs::read_to_string("assets/shaders/ui_vertex.glsl")
-    //     .expect("Failed to read vertex shader source code from file");
-
-    let ui_vertex_path = format!("{}/shaders/ui_vertex.glsl", assets_path);
-    let ui_vertex_shader_src = std::fs::read_to_string(ui_vertex_path)
-        .expect("Failed to read vertex shader source code from file");
-
-    // let ui_fragment_shader_src = std::fs::read_to_string("assets/shaders/ui_fragment.glsl")
-    //     .expect("Failed to read fragment shader source code from file");
-
-    let ui_fragment_path = format!("{}/shaders/ui_fragment.glsl", assets_path);
-    let ui_fragment_shader_src = std::fs::read_to_string(ui_fragment_path)
-        .expect("Failed to read fragment shader source code from file");
-
-    let ui_program = glium::Program::from_source(
-        &display,
-        ui_vertex_shader_src.as_str(),
-        ui_fragment_shader_src.as_str(),
-        None,
-    )
-    .unwrap();
-
-    let camera = Camera::new(
-        [0.0, 0.0, 0.0f32],
-        [0.0, 0.0, 0.0f32],
-        0.05,
-        0.05,
-        terminal_res,
-    );
-
-    let game = Game::new(camera, assets_path.to_string());
-
-    (
-        terminal_res,
-        terminal_fb,
-        event_loop,
-        display,
-        program,
-        ui_program,
-        params,
-        ui_params,
-        game,
-    )
+    /// Called after the terminal was resized to `terminal_res` columns and rows
+    fn on_resize(&mut self, _terminal_res: (u32, u32), _game: &mut Game, _display: &dyn Upload) {}
+
+    /// Called after `Game::set_scene` switched away from the scene at index `previous`
+    fn on_scene_changed(&mut self, _previous: usize, _game: &mut Game, _display: &dyn Upload) {}
+
+    /// Called once when the engine shuts down
+    fn on_exit(&mut self, _game: &mut Game) {}
+}
+
+/// A `GameState` made of a game loop and a game init function, as created by `game_loop!` and `game_init!`
+pub struct Callbacks<F, G> {
+    game_loop: F,
+    game_init: G,
+}
+
+impl<F, G> Callbacks<F, G>
+where
+    F: FnMut(Option<&DeviceState>, (u32, u32), &mut Game, &dyn Upload),
+    G: FnMut((u32, u32), &mut Game, &dyn Upload),
+{
+    /// Calls `game_init` from `GameState::init` and `game_loop` from `GameState::fixed_update`
+    pub fn new(game_loop: F, game_init: G) -> Callbacks<F, G> {
+        Callbacks { game_loop, game_init }
+    }
+}
+
+impl<F, G> GameState for Callbacks<F, G>
+where
+    F: FnMut(Option<&DeviceState>, (u32, u32), &mut Game, &dyn Upload),
+    G: FnMut((u32, u32), &mut Game, &dyn Upload),
+{
+    fn init(&mut self, terminal_res: (u32, u32), game: &mut Game, display: &dyn Upload) {
+        (self.game_init)(terminal_res, game, display);
+    }
+
+    fn fixed_update(
+        &mut self,
+        device_state: Option<&DeviceState>,
+        terminal_res: (u32, u32),
+        game: &mut Game,
+        display: &dyn Upload,
+    ) {
+        (self.game_loop)(device_state, terminal_res, game, display);
+    }
+}
+
+/// Where the camera starts and how fast it moves
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct CameraSettings {
+    pub position: [f32; 3],
+    pub rotation: [f32; 3],
+    pub move_speed: f32,
+    pub mouse_sensitivity: f32,
+}
+
+impl Default for CameraSettings {
+    fn default() -> Self {
+        CameraSettings {
+            position: [0.0, 0.0, 0.0f32],
+            rotation: [0.0, 0.0, 0.0f32],
+            move_speed: 0.05,
+            mouse_sensitivity: 0.05,
+        }
+    }
+}
+
+/// What `Engine` renders with, see `EngineBuilder::renderer`
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Default)]
+pub enum Backend {
+    /// OpenGL in a hidden window, falling back to `Backend::Software` when no window or context can be created
+    #[default]
+    Auto,
+    /// OpenGL in a hidden window, `EngineBuilder::build` fails without one
+    OpenGl,
+    /// `SoftwareRenderer` on the CPU without a window, for machines without a display server or OpenGL driver
+    Software,
+}
+
+/// Configures and starts the engine, e.g. `EngineBuilder::new(assets_path).clear_color(sky).run(game_state)`.
+#[derive(Clone, Debug)]
+pub struct EngineBuilder {
+    assets_path: String,
+    render: RenderSettings,
+    camera: CameraSettings,
+    timestep: Duration,
+    backend: Backend,
+}
+
+impl EngineBuilder {
+    /// Starts with the default settings, loading shaders and assets from `assets_path`
+    pub fn new(assets_path: &str) -> EngineBuilder {
+        EngineBuilder {
+            assets_path: assets_path.to_string(),
+            render: RenderSettings::default(),
+            camera: CameraSettings::default(),
+            timestep: Duration::from_nanos(16_666_667),
+            backend: Backend::default(),
+        }
+    }
+
+    /// Replace all render settings
+    pub fn render_settings(mut self, settings: RenderSettings) -> EngineBuilder {
+        self.render = settings;
+        self
+    }
+
+    /// Set the color of the sky
+    pub fn clear_color(mut self, color: Color) -> EngineBuilder {
+        self.render.clear_color = color;
+        self
+    }
+
+    /// Set the direction the light comes from
+    pub fn light(mut self, direction: [f32; 3]) -> EngineBuilder {
+        self.render.light = direction;
+        self
+    }
+
+    /// Set the shader files, relative to the assets folder
+    pub fn shaders(mut self, shaders: ShaderPaths) -> EngineBuilder {
+        self.render.shaders = shaders;
+        self
+    }
+
+    /// Set where the camera starts and how fast it moves
+    pub fn camera(mut self, settings: CameraSettings) -> EngineBuilder {
+        self.camera = settings;
+        self
+    }
+
+    /// Set the fixed time between two calls of the game loop, 60Hz by default
+    pub fn timestep(mut self, timestep: Duration) -> EngineBuilder {
+        self.timestep = timestep;
+        self
+    }
+
+    /// Set what the engine renders with, `Backend::Auto` by default
+    pub fn renderer(mut self, backend: Backend) -> EngineBuilder {
+        self.backend = backend;
+        self
+    }
+
+    /// Detects the terminal, opens the hidden window and loads the shaders, or sets up the software renderer
+    pub fn build(self) -> Result<Engine, EngineError> {
+        let terminal_res = terminal_size().ok_or(EngineError::NotATerminal)?;
+        let terminal_res: (u32, u32) = (u32::from(terminal_res.0 .0), u32::from(terminal_res.1 .0));
+
+        let capabilities = TerminalCapabilities::detect();
+        let session = TerminalSession::enter().map_err(EngineError::Terminal)?;
+
+        let (pixel_width, pixel_height) = OutputSettings::default()
+            .cell_mode
+            .pixel_size(terminal_res.0 as usize, terminal_res.1 as usize);
+        let mut terminal_fb = TerminalFrameBuffer::new(pixel_width, pixel_height, Color { r: 0, g: 0, b: 0 });
+        terminal_fb.set_frame_sync(capabilities.frame_sync());
+
+        let window = match self.backend {
+            Backend::OpenGl => Some(Window::open(&self.assets_path, &self.render, terminal_res)?),
+            // winit panics instead of failing when it can not reach a display server
+            Backend::Auto if !HeadlessRenderer::has_display() => None,
+            // missing or broken shaders are reported instead of hidden by the fallback
+            Backend::Auto => match Window::open(&self.assets_path, &self.render, terminal_res) {
+                Ok(window) => Some(window),
+                Err(EngineError::Context(_)) => None,
+                Err(error) => return Err(error),
+            },
+            Backend::Software => None,
+        };
+        let (event_loop, display, renderer) = match window {
+            Some(window) => (Some(window.event_loop), Some(window.display), window.renderer),
+            None => (None, None, Box::new(SoftwareRenderer::with_settings(&self.render)) as Box<dyn Renderer>),
+        };
+
+        let camera = Camera::new(
+            self.camera.position,
+            self.camera.rotation,
+            self.camera.move_speed,
+            self.camera.mouse_sensitivity,
+            terminal_res,
+        );
+
+        let game = Game::with_capabilities(camera, self.assets_path, capabilities);
+
+        Ok(Engine {
+            session,
+            terminal_res,
+            terminal_fb,
+            event_loop,
+            display,
+            renderer,
+            game,
+            timestep: self.timestep,
+        })
+    }
+
+    /// Builds the engine and runs `game_state` until it exits, see `Engine::run`
+    pub fn run<S: GameState + 'static>(self, game_state: S) -> Result<i32, EngineError> {
+        Ok(self.build()?.run(game_state))
+    }
+}
+
+/// The hidden window OpenGL renders in
+struct Window {
+    event_loop: glutin::event_loop::EventLoop<()>,
+    display: glium::Display,
+    renderer: Box<dyn Renderer>,
+}
+
+impl Window {
+    /// Opens the window at the size of the terminal and loads the shaders of `settings` from `assets_path`
+    fn open(assets_path: &str, settings: &RenderSettings, terminal_res: (u32, u32)) -> Result<Window, EngineError> {
+        let event_loop = glutin::event_loop::EventLoop::new();
+        let wb = glutin::window::WindowBuilder::new()
+            .with_visible(false)
+            .with_inner_size(glutin::dpi::LogicalSize::new(
+                terminal_res.0,
+                terminal_res.1,
+            ));
+
+        let cb = glutin::ContextBuilder::new().with_depth_buffer(24);
+        let display =
+            glium::Display::new(wb, cb, &event_loop).map_err(|error| EngineError::Context(error.to_string()))?;
+
+        let renderer = GlRenderer::with_settings(display.clone(), assets_path, settings)?;
+
+        Ok(Window {
+            event_loop,
+            display,
+            renderer: Box::new(renderer),
+        })
+    }
+}
+
+/// The window, renderer, terminal and game created by `EngineBuilder::build`
+pub struct Engine {
+    session: TerminalSession,
+    terminal_res: (u32, u32),
+    terminal_fb: TerminalFrameBuffer,
+    /// `None` with the software renderer, which runs without a window
+    event_loop: Option<glutin::event_loop::EventLoop<()>>,
+    display: Option<glium::Display>,
+    renderer: Box<dyn Renderer>,
+    game: Game,
+    timestep: Duration,
+}
+
+impl Engine {
+    /// Get the game, e.g. to set it up before `run`
+    pub fn get_game_mut(&mut self) -> &mut Game {
+        &mut self.game
+    }
+
+    /// Get where objects have to be uploaded to, the window's display or `CpuOnly` for the software renderer
+    pub fn get_display(&self) -> &dyn Upload {
+        upload_target(&self.display)
+    }
+
+    /// Checks if frames are rendered with OpenGL rather than on the CPU
+    pub fn is_opengl(&self) -> bool {
+        self.display.is_some()
+    }
+
+    /// Runs the hooks of `game_state` and draws a frame to the terminal after every update, until
+    /// `Game::request_exit` is called, the process gets SIGINT or SIGTERM or the window is closed.
+    /// Restores the terminal and returns the exit code on platforms that allow returning from the event loop,
+    /// on the others the process exits with it. Without a window the engine runs its own loop and always returns.
+    pub fn run<S: GameState + 'static>(self, game_state: S) -> i32 {
+        let Engine {
+            session,
+            terminal_res,
+            terminal_fb,
+            event_loop,
+            display,
+            renderer,
+            game,
+            timestep,
+        } = self;
+
+        let now = Instant::now();
+        let mut runner = Runner {
+            game_state,
+            game,
+            terminal_fb,
+            terminal_res,
+            scene_index: 0,
+            display,
+            renderer,
+            device_state: has_input().then(DeviceState::new),
+            recording: None,
+            accumulator: Duration::new(0, 0),
+            timestep,
+            next_frame_time: now,
+            last_update: now,
+            session: Some(session),
+            signal_handlers: Some(signal::install_handlers()),
+        };
+        runner.init();
+
+        let mut event_loop = match event_loop {
+            Some(event_loop) => event_loop,
+            None => loop {
+                if let Some(code) = runner.poll().or_else(|| runner.frame()) {
+                    runner.finish();
+                    return code;
+                }
+            },
+        };
+
+        let event_handler = move |event: glutin::event::Event<'_, ()>,
+                                  _: &glutin::event_loop::EventLoopWindowTarget<()>,
+                                  control_flow: &mut glutin::event_loop::ControlFlow| {
+            if let glutin::event::Event::LoopDestroyed = event {
+                runner.finish();
+                return;
+            }
+
+            if let Some(code) = runner.poll() {
+                *control_flow = glutin::event_loop::ControlFlow::ExitWithCode(code);
+                return;
+            }
+
+            *control_flow = glutin::event_loop::ControlFlow::Poll;
+
+            match event {
+                glutin::event::Event::WindowEvent {
+                    event: glutin::event::WindowEvent::CloseRequested,
+                    ..
+                } => {
+                    *control_flow = glutin::event_loop::ControlFlow::Exit;
+                }
+
+                glutin::event::Event::MainEventsCleared => {
+                    if let Some(code) = runner.frame() {
+                        *control_flow = glutin::event_loop::ControlFlow::ExitWithCode(code);
+                    }
+                }
+
+                _ => {}
+            }
+        };
+
+        #[cfg(any(
+            target_os = "windows",
+            target_os = "macos",
+            target_os = "linux",
+            target_os = "dragonfly",
+            target_os = "freebsd",
+            target_os = "netbsd",
+            target_os = "openbsd",
+        ))]
+        {
+            use glutin::platform::run_return::EventLoopExtRunReturn;
+            event_loop.run_return(event_handler)
+        }
+        #[cfg(not(any(
+            target_os = "windows",
+            target_os = "macos",
+            target_os = "linux",
+            target_os = "dragonfly",
+            target_os = "freebsd",
+            target_os = "netbsd",
+            target_os = "openbsd",
+        )))]
+        event_loop.run(event_handler)
+    }
+}
+
+/// Checks if the keyboard and mouse can be read, `DeviceState` needs an X display on Linux and the BSDs
+fn has_input() -> bool {
+    if cfg!(any(
+        target_os = "linux",
+        target_os = "dragonfly",
+        target_os = "freebsd",
+        target_os = "netbsd",
+        target_os = "openbsd",
+    )) {
+        std::env::var_os("DISPLAY").is_some()
+    } else {
+        true
+    }
+}
+
+/// Everything `Engine::run` keeps between frames, driven by the event loop of the window or by a plain loop without one
+struct Runner<S> {
+    game_state: S,
+    game: Game,
+    terminal_fb: TerminalFrameBuffer,
+    terminal_res: (u32, u32),
+    scene_index: usize,
+    /// `None` with the software renderer
+    display: Option<glium::Display>,
+    renderer: Box<dyn Renderer>,
+    device_state: Option<DeviceState>,
+    recording: Option<PathBuf>,
+    accumulator: Duration,
+    timestep: Duration,
+    next_frame_time: Instant,
+    last_update: Instant,
+    session: Option<TerminalSession>,
+    signal_handlers: Option<SignalHandlers>,
+}
+
+/// Where the hooks upload objects to, the window's display or `CpuOnly` without one
+fn upload_target(display: &Option<glium::Display>) -> &dyn Upload {
+    match display {
+        Some(display) => display,
+        None => &CpuOnly,
+    }
+}
+
+/// Moves the cursor to where `Game::set_cursor_position` asked for, right away so the next update reads it back
+fn move_cursor(game: &mut Game, display: &Option<glium::Display>) {
+    if let Some((x, y)) = game.cursor_position.take() {
+        if let Some(display) = display {
+            // not every platform lets windows move the cursor
+            let _ = display
+                .gl_window()
+                .window()
+                .set_cursor_position(glutin::dpi::PhysicalPosition::new(x, y));
+        }
+    }
+}
+
+impl<S: GameState> Runner<S> {
+    fn init(&mut self) {
+        let display = upload_target(&self.display);
+        self.game_state.init(self.terminal_res, &mut self.game, display);
+        self.scene_index = self.game.get_scene_index();
+        move_cursor(&mut self.game, &self.display);
+    }
+
+    /// Picks up signals, exit requests, terminal resizes, output settings and recordings.
+    /// Returns the exit code once the engine has to stop.
+    fn poll(&mut self) -> Option<i32> {
+        if let Some(code) = signal::take_exit_code() {
+            self.game.request_exit_with_code(code);
+        }
+        if let Some(code) = self.game.get_exit_request() {
+            return Some(code);
+        }
+
+        let display = upload_target(&self.display);
+
+        // Check res and update if changed, the last known size is kept while it can not be read
+        let terminal_res = terminal_size().map_or(self.terminal_res, |(width, height)| {
+            (u32::from(width.0), u32::from(height.0))
+        });
+        if terminal_res != self.terminal_res {
+            self.terminal_res = terminal_res;
+            self.terminal_fb.resize_terminal(terminal_res.0 as usize, terminal_res.1 as usize);
+            self.game_state.on_resize(terminal_res, &mut self.game, display);
+        }
+
+        if *self.terminal_fb.settings() != self.game.output {
+            self.terminal_fb.set_settings(self.game.output.clone());
+        }
+
+        if let Some(error) = self.terminal_fb.take_recording_error() {
+            self.game.fail_recording(error);
+        }
+        if self.recording.as_deref() != self.game.get_recording_path() {
+            let path = self.game.get_recording_path().map(Path::to_path_buf);
+            if let Some(recorder) = self.terminal_fb.stop_recording() {
+                if let Err(error) = recorder.finish() {
+                    self.game.fail_recording(error);
+                }
+            }
+            if let Some(path) = path {
+                let started = AsciicastRecorder::create(&path, terminal_res.0 as usize, terminal_res.1 as usize)
+                    .and_then(|recorder| self.terminal_fb.start_recording(recorder));
+                match started {
+                    Ok(_) => self.game.start_recording(path),
+                    Err(error) => self.game.fail_recording(error),
+                }
+            }
+            self.recording = self.game.get_recording_path().map(Path::to_path_buf);
+        }
+
+        None
+    }
+
+    /// Runs the updates due since the last frame and draws the next one.
+    /// Returns an exit code when the terminal is gone.
+    fn frame(&mut self) -> Option<i32> {
+        let display = upload_target(&self.display);
+
+        let now = Instant::now();
+        self.accumulator += now - self.next_frame_time;
+        self.next_frame_time = now;
+
+        self.game.delta_time = self.accumulator.as_secs_f32();
+
+        while self.accumulator >= self.timestep {
+            //--------------------------------- Sort of a game loop ---------------------------------
+
+            self.game_state.fixed_update(self.device_state.as_ref(), self.terminal_res, &mut self.game, display);
+            move_cursor(&mut self.game, &self.display);
+
+            self.accumulator -= self.timestep;
+        }
+
+        self.game_state.update((now - self.last_update).as_secs_f32(), &mut self.game, display);
+        move_cursor(&mut self.game, &self.display);
+        self.last_update = now;
+
+        if self.game.get_scene_index() != self.scene_index {
+            let previous = self.scene_index;
+            self.scene_index = self.game.get_scene_index();
+            self.game_state.on_scene_changed(previous, &mut self.game, display);
+        }
+
+        //--------------------------------- Render (post update) ---------------------------------
+
+        render::render_to_framebuffer(self.renderer.as_mut(), &self.game, &self.game.camera, &mut self.terminal_fb);
+
+        if self.terminal_fb.draw_frame().is_err() {
+            // the terminal is gone, e.g. closed while the game was running
+            return Some(1);
+        }
+        self.game.cells_written = self.terminal_fb.cells_written();
+        None
+    }
+
+    /// Restores the terminal, finishes the recording and runs `GameState::on_exit`
+    fn finish(&mut self) {
+        // fails when the terminal is already gone, there is nothing left to clear then
+        let _ = self.terminal_fb.restore_terminal();
+        if let Some(error) = self.terminal_fb.take_recording_error() {
+            self.game.fail_recording(error);
+        }
+        if let Some(recorder) = self.terminal_fb.stop_recording() {
+            if let Err(error) = recorder.finish() {
+                self.game.fail_recording(error);
+            }
+        }
+        self.game_state.on_exit(&mut self.game);
+        // leaves the alternate screen and gives Ctrl+C back, also where the event loop never returns
+        self.session.take();
+        self.signal_handlers.take();
+    }
 }
 
 #[macro_export]
 /// The `game_loop` macro creates a game loop function with ability to pass additional parameters.
 /// NOTE: The game loop function must have the following signature:
-/// `fn(&DeviceState, (u32, u32), &mut Game, &glium::Display, ...)`
+/// `fn(Option<&DeviceState>, (u32, u32), &mut Game, &dyn Upload, ...)`
 /// where `...` is the list of additional parameters.
 macro_rules! game_loop {
     ($game_loop_func:expr $(, $param:expr)* $(,)?) => {
@@ -235,7 +774,7 @@ macro_rules! game_loop {
 #[macro_export]
 /// The `game_init` macro creates a game init function with ability to pass additional parameters.
 /// NOTE: The game init function must have the following signature:
-/// `fn( (u32,u32), &mut Game, &glium::Display, ...)`
+/// `fn( (u32,u32), &mut Game, &dyn Upload, ...)`
 /// where `...` is the list of additional parameters.
 macro_rules! game_init {
     ($game_init_func:expr $(, $param:expr)* $(,)?) => {
@@ -251,187 +790,77 @@ macro_rules! game_init {
 /// NOTE: recommended to use macro `game_loop!` to create the game loop function and `game_init!` to create the game init function.
 macro_rules! init_engine {
     ($game_loop_func:expr, $game_init_func:expr, $assets_path:expr) => {
-        ascii_opengl_rust::engine::core::run_event_loop(ascii_opengl_rust::engine::core::init($assets_path), $game_loop_func, $game_init_func);
+        match ascii_opengl_rust::engine::core::EngineBuilder::new($assets_path)
+            .run(ascii_opengl_rust::engine::core::Callbacks::new($game_loop_func, $game_init_func))
+        {
+            Ok(0) => {}
+            Ok(code) => std::process::exit(code),
+            Err(error) => {
+                eprintln!("Failed to start the engine: {}", error);
+                std::process::exit(1);
+            }
+        }
     };
 }
 
-pub fn run_event_loop<F, G>(init_type: InitType, mut game_loop: F, mut game_init: G)
+/// What `init` creates and `run_event_loop` runs
+#[deprecated(note = "use `EngineBuilder::build`, which returns an `Engine`")]
+pub type InitType = Engine;
+
+/// Opens the window and detects the terminal with the default settings, panics when the engine can not start.
+/// Always renders with OpenGL, the callbacks of `run_event_loop` get the window's display.
+#[deprecated(note = "use `EngineBuilder::new(assets_path).build()`")]
+#[allow(deprecated)]
+pub fn init(assets_path: &str) -> InitType {
+    EngineBuilder::new(assets_path)
+        .renderer(Backend::OpenGl)
+        .build()
+        .expect("Failed to start the engine")
+}
+
+/// Runs `game_init` once and `game_loop` at the fixed timestep, then exits the process with the exit code
+#[deprecated(note = "use `Engine::run` with a `GameState`, or `Callbacks` for closures")]
+#[allow(deprecated)]
+pub fn run_event_loop<F, G>(init_type: InitType, game_loop: F, game_init: G)
 where
     F: FnMut(&DeviceState, (u32, u32), &mut Game, &glium::Display) + 'static,
-    G: FnMut((u32, u32),&mut Game, &glium::Display) + 'static,
+    G: FnMut((u32, u32), &mut Game, &glium::Display) + 'static,
 {
-    let (
-        terminal_res,
-        terminal_fb,
-        event_loop,
+    let display = init_type.display.clone().expect("`init` always opens a window");
+    let callbacks = DisplayCallbacks {
         display,
-        program,
-        ui_program,
-        params,
-        ui_params,
-        mut game,
-    ) = init_type;
-
-    game_init(terminal_res,&mut game, &display);
-
-    let mut terminal_res = terminal_res;
-    let mut terminal_fb = terminal_fb;
-
-    let light = [1.4, 0.4, -0.7f32];
-    let device_state = DeviceState::new();
-    let mut accumulator = std::time::Duration::new(0, 0);
-    let fixed_timestep = std::time::Duration::from_nanos(16_666_667);
-    let mut next_frame_time = std::time::Instant::now();
-
-    event_loop.run(move |event, _, control_flow| {
-        let texture = glium::texture::Texture2d::empty_with_format(
-            &display,
-            glium::texture::UncompressedFloatFormat::U8U8U8U8,
-            glium::texture::MipmapsOption::NoMipmap,
-            terminal_res.0,
-            terminal_res.1,
-        )
-        .unwrap();
-
-        // Create a depth buffer for off-screen rendering
-        let depthbuffer = glium::framebuffer::DepthRenderBuffer::new(
-            &display,
-            glium::texture::DepthFormat::F32,
-            terminal_res.0,
-            terminal_res.1,
-        )
-        .unwrap();
-
-        // Create a framebuffer for off-screen rendering
-        let mut framebuffer: glium::framebuffer::SimpleFrameBuffer =
-            glium::framebuffer::SimpleFrameBuffer::with_depth_buffer(
-                &display,
-                &texture,
-                &depthbuffer,
-            )
-            .unwrap();
-
-        // Check res and update if changed
-        let new_terminal_res = terminal_size().unwrap();
-        let new_terminal_res: (u32, u32) = (
-            u32::from(new_terminal_res.0 .0),
-            u32::from(new_terminal_res.1 .0),
-        );
-        if new_terminal_res != terminal_res {
-            terminal_res = new_terminal_res;
-            terminal_fb = TerminalFrameBuffer::new(
-                (terminal_res.0 as usize) / 2,
-                terminal_res.1 as usize,
-                Color { r: 0, g: 0, b: 0 },
-            );
-            TerminalFrameBuffer::update_res(
-                &mut terminal_fb,
-                new_terminal_res.0 as usize,
-                new_terminal_res.1 as usize,
-            );
-        }
-
-        *control_flow = glutin::event_loop::ControlFlow::Poll;
-
-        match event {
-            glutin::event::Event::WindowEvent {
-                event: glutin::event::WindowEvent::CloseRequested,
-                ..
-            } => {
-                *control_flow = glutin::event_loop::ControlFlow::Exit;
-                return;
-            }
-
-            glutin::event::Event::MainEventsCleared => {
-                let now = std::time::Instant::now();
-                accumulator += now - next_frame_time;
-                next_frame_time = now;
-
-                game.delta_time = accumulator.as_secs_f32();
-
-                while accumulator >= fixed_timestep {
-                    //--------------------------------- Sort of a game loop ---------------------------------
-
-                    game_loop(&device_state, terminal_res, &mut game, &display);
-
-                    accumulator -= fixed_timestep;
-                }
-
-                //--------------------------------- Render (post update) ---------------------------------
-
-                framebuffer
-                    .clear_color_and_depth((105.0 / 255.0, 109.0 / 255.0, 219.0 / 255.0, 1.0), 1.0);
-
-                for object in &game.get_scene().objects {
-                    let uniforms = uniform! {
-                        model: object.model,
-                        view: game.camera.view_matrix(),
-                        perspective: game.camera.perspective_matrix(),
-                        u_light: light,
-                        tex: &object.texture,
-                    };
-
-                    framebuffer
-                        .draw(&object.vb, &object.ib, &program, &uniforms, &params)
-                        .unwrap();
-                }
-
-                //--------------------------------- UI ---------------------------------
-
-                for ui_elem in &game.get_ui_elems().elems {
-                    let behavior = match ui_elem.texture_filter {
-                        TextureFilter::Nearest => glium::uniforms::SamplerBehavior {
-                            minify_filter: glium::uniforms::MinifySamplerFilter::Nearest,
-                            magnify_filter: glium::uniforms::MagnifySamplerFilter::Nearest,
-                            ..Default::default()
-                        },
-                        TextureFilter::Linear => glium::uniforms::SamplerBehavior {
-                            minify_filter: glium::uniforms::MinifySamplerFilter::Linear,
-                            magnify_filter: glium::uniforms::MagnifySamplerFilter::Linear,
-                            ..Default::default()
-                        },
-                    };
-
-                    let uniforms = uniform! {
-                        tex: glium::uniforms::Sampler(&ui_elem.texture, behavior),
-                        // tex: &ui_elem.texture,
-                    };
-
-                    framebuffer
-                        .draw(&ui_elem.vb, &ui_elem.ib, &ui_program, &uniforms, &ui_params)
-                        .unwrap();
-                }
-            }
-
-            _ => {
-                return;
-            }
-        }
+        game_loop,
+        game_init,
+    };
+    std::process::exit(init_type.run(callbacks));
+}
 
-        //get pixels from display
-        //TODO: use framebuffer.read_to_pixel_buffer
-        let pixels: glium::texture::RawImage2d<u8> = texture.read();
-        terminal_fb.clear();
-        for i in 0..pixels.data.len() / 4 {
-            let r = pixels.data[i * 4];
-            let g = pixels.data[i * 4 + 1];
-            let b = pixels.data[i * 4 + 2];
-
-            let mut x = ((i % (pixels.width as usize)) * (terminal_res.0 as usize))
-                / (pixels.width as usize);
-            if x >= (terminal_res.0 as usize) / 4 {
-                x -= (terminal_res.0 as usize) / 4;
-            } else {
-                x = 0;
-            }
-            let y = (terminal_res.1 as usize)
-                - ((i / (pixels.width as usize)) * (terminal_res.1 as usize))
-                    / (pixels.height as usize);
+/// The callbacks of `run_event_loop`, which take the window's display instead of an `Upload`
+struct DisplayCallbacks<F, G> {
+    display: glium::Display,
+    game_loop: F,
+    game_init: G,
+}
 
-            let color = Color { r, g, b };
+impl<F, G> GameState for DisplayCallbacks<F, G>
+where
+    F: FnMut(&DeviceState, (u32, u32), &mut Game, &glium::Display),
+    G: FnMut((u32, u32), &mut Game, &glium::Display),
+{
+    fn init(&mut self, terminal_res: (u32, u32), game: &mut Game, _display: &dyn Upload) {
+        (self.game_init)(terminal_res, game, &self.display);
+    }
 
-            terminal_fb.set_pixel(x, y, color);
+    fn fixed_update(
+        &mut self,
+        device_state: Option<&DeviceState>,
+        terminal_res: (u32, u32),
+        game: &mut Game,
+        _display: &dyn Upload,
+    ) {
+        // the window needs a display server, so the keyboard and mouse can be read as well
+        if let Some(device_state) = device_state {
+            (self.game_loop)(device_state, terminal_res, game, &self.display);
         }
-        terminal_fb.draw_frame();
-    });
+    }
 }
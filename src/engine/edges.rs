@@ -0,0 +1,201 @@
+use super::ascii_render::Color;
+
+/// Vertex shader of the pass that renders view space normals and depth for edge detection
+pub const EDGE_VERTEX_SHADER: &str = r#"
+#version 150
+
+in vec3 position;
+in vec3 normal;
+
+out vec3 v_normal;
+out float v_depth;
+
+uniform mat4 perspective;
+uniform mat4 view;
+uniform mat4 model;
+
+void main() {
+  mat4 modelview = view * model;
+  v_normal = transpose(inverse(mat3(modelview))) * normal;
+  vec4 view_position = modelview * vec4(position, 1.0);
+  v_depth = view_position.z;
+  gl_Position = perspective * view_position;
+}
+"#;
+
+/// Fragment shader of the edge pass, writes the normal in `rg`, the logarithmic depth in `b` and coverage in `a`.
+/// `znear` and `zfar` are the clipping planes of the perspective, `matrices::ZNEAR` and `matrices::ZFAR`.
+pub const EDGE_FRAGMENT_SHADER: &str = r#"
+#version 140
+
+in vec3 v_normal;
+in float v_depth;
+
+out vec4 color;
+
+uniform float znear;
+uniform float zfar;
+
+void main() {
+  vec3 normal = normalize(v_normal);
+  float depth = log2(max(v_depth, znear) / znear) / log2(zfar / znear);
+  color = vec4(normal.xy * 0.5 + 0.5, depth, 1.0);
+}
+"#;
+
+/// Value the edge pass target is cleared to, a surface facing the camera at the far plane
+pub const EDGE_CLEAR_COLOR: (f32, f32, f32, f32) = (0.5, 0.5, 1.0, 0.0);
+
+/// Orientation of an edge running through a pixel.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Default)]
+pub enum Edge {
+    #[default]
+    None,
+    Horizontal,
+    Vertical,
+    /// Going up to the right (`/`)
+    Rising,
+    /// Going down to the right (`\`)
+    Falling,
+}
+
+impl Edge {
+    /// Line glyph drawn for the edge
+    pub fn glyph(&self) -> Option<char> {
+        match self {
+            Edge::None => None,
+            Edge::Horizontal => Some('-'),
+            Edge::Vertical => Some('|'),
+            Edge::Rising => Some('/'),
+            Edge::Falling => Some('\\'),
+        }
+    }
+}
+
+/// Settings of the outline pass, which overrides cells on strong depth or normal edges with line glyphs.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct EdgeSettings {
+    /// Sobel response of the logarithmic depth (0.0 - 1.0 between the near and far plane) that counts as an edge
+    pub depth_threshold: f32,
+    /// Sobel response of the view space normals that counts as an edge
+    pub normal_threshold: f32,
+    /// Color the line glyphs are drawn in
+    pub color: Color,
+}
+
+impl Default for EdgeSettings {
+    fn default() -> Self {
+        EdgeSettings {
+            depth_threshold: 0.03,
+            normal_threshold: 1.0,
+            color: Color { r: 0, g: 0, b: 0 },
+        }
+    }
+}
+
+/// Runs a Sobel filter over the output of the edge pass and returns the edge through every pixel.
+/// `pixels` holds `width` x `height` RGBA values as written by `EDGE_FRAGMENT_SHADER`, top row first.
+pub fn detect_edges(pixels: &[[u8; 4]], width: usize, height: usize, settings: &EdgeSettings) -> Vec<Edge> {
+    let mut edges = vec![Edge::None; width * height];
+    if width < 3 || height < 3 {
+        return edges;
+    }
+
+    let channel = |x: usize, y: usize, channel: usize| f32::from(pixels[y * width + x][channel]) / 255.0;
+    let sobel = |x: usize, y: usize, c: usize| {
+        let gx = (channel(x + 1, y - 1, c) + 2.0 * channel(x + 1, y, c) + channel(x + 1, y + 1, c))
+            - (channel(x - 1, y - 1, c) + 2.0 * channel(x - 1, y, c) + channel(x - 1, y + 1, c));
+        let gy = (channel(x - 1, y + 1, c) + 2.0 * channel(x, y + 1, c) + channel(x + 1, y + 1, c))
+            - (channel(x - 1, y - 1, c) + 2.0 * channel(x, y - 1, c) + channel(x + 1, y - 1, c));
+        (gx, gy)
+    };
+
+    for y in 1..height - 1 {
+        for x in 1..width - 1 {
+            // compare every channel against its threshold and follow the one that crosses it the most
+            let candidates = [
+                (sobel(x, y, 2), settings.depth_threshold),
+                (sobel(x, y, 0), settings.normal_threshold / 2.0),
+                (sobel(x, y, 1), settings.normal_threshold / 2.0),
+            ];
+            let strongest = candidates
+                .iter()
+                .map(|((gx, gy), threshold)| ((gx, gy), (gx * gx + gy * gy).sqrt() / threshold))
+                .max_by(|a, b| a.1.total_cmp(&b.1));
+
+            if let Some(((gx, gy), strength)) = strongest {
+                if strength >= 1.0 {
+                    edges[y * width + x] = orientation(*gx, *gy);
+                }
+            }
+        }
+    }
+
+    edges
+}
+
+/// Orientation of the edge perpendicular to the gradient `gx`, `gy` (y pointing down)
+fn orientation(gx: f32, gy: f32) -> Edge {
+    let angle = (-gy).atan2(gx).to_degrees().rem_euclid(180.0);
+    // the edge runs at a right angle to the gradient
+    match (angle + 90.0).rem_euclid(180.0) {
+        a if !(22.5..157.5).contains(&a) => Edge::Horizontal,
+        a if a < 67.5 => Edge::Rising,
+        a if a < 112.5 => Edge::Vertical,
+        _ => Edge::Falling,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Edge pass output with a flat normal facing the camera and the depth given per pixel
+    fn depth_image(width: usize, height: usize, depth: impl Fn(usize, usize) -> u8) -> Vec<[u8; 4]> {
+        (0..width * height)
+            .map(|index| [128, 128, depth(index % width, index / width), 255])
+            .collect()
+    }
+
+    #[test]
+    fn edges_run_across_the_gradient() {
+        assert_eq!(orientation(1.0, 0.0), Edge::Vertical);
+        assert_eq!(orientation(-1.0, 0.0), Edge::Vertical);
+        assert_eq!(orientation(0.0, 1.0), Edge::Horizontal);
+        assert_eq!(orientation(1.0, 1.0), Edge::Rising);
+        assert_eq!(orientation(1.0, -1.0), Edge::Falling);
+    }
+
+    #[test]
+    fn depth_steps_become_edges() {
+        let (width, height) = (6, 4);
+        let pixels = depth_image(width, height, |x, _| if x < 3 { 0 } else { 255 });
+        let edges = detect_edges(&pixels, width, height, &EdgeSettings::default());
+
+        for y in 0..height {
+            for x in 0..width {
+                let expected = if (1..height - 1).contains(&y) && (x == 2 || x == 3) {
+                    Edge::Vertical
+                } else {
+                    Edge::None
+                };
+                assert_eq!(edges[y * width + x], expected, "pixel {x}, {y}");
+            }
+        }
+
+        let pixels = depth_image(width, width, |x, y| if x + y < width { 0 } else { 255 });
+        let edges = detect_edges(&pixels, width, width, &EdgeSettings::default());
+        assert!(edges.contains(&Edge::Rising));
+        assert!(edges.iter().all(|edge| matches!(edge, Edge::None | Edge::Rising)));
+    }
+
+    #[test]
+    fn flat_and_tiny_images_have_no_edges() {
+        let pixels = depth_image(5, 5, |_, _| 100);
+        let edges = detect_edges(&pixels, 5, 5, &EdgeSettings::default());
+        assert!(edges.iter().all(|edge| *edge == Edge::None));
+
+        let pixels = depth_image(2, 5, |x, _| if x == 0 { 0 } else { 255 });
+        assert_eq!(detect_edges(&pixels, 2, 5, &EdgeSettings::default()), vec![Edge::None; 10]);
+    }
+}
@@ -0,0 +1,208 @@
+use super::ascii_render::{Cell, Color};
+use super::palette::{self, ColorDepth};
+use std::io::Write;
+
+/// Turns cells into escape sequences, keeping track of the terminal's cursor and colors
+/// so sequences that would not change anything are left out.
+#[derive(Clone)]
+pub struct AnsiEncoder {
+    /// Leave out redundant sequences. When unset every cell gets a cursor position and both colors.
+    pub coalesce: bool,
+    pub color_depth: ColorDepth,
+    /// Number of columns the cells cover, the cursor is not tracked past the last one
+    pub line_width: usize,
+    /// `(row, column)` of the cursor, 1-based, `None` when unknown
+    cursor: Option<(usize, usize)>,
+    /// Current colors, `None` when unknown and `Some(None)` for the terminal default
+    foreground: Option<Option<Color>>,
+    background: Option<Option<Color>>,
+}
+
+impl AnsiEncoder {
+    pub fn new(coalesce: bool, color_depth: ColorDepth, line_width: usize) -> AnsiEncoder {
+        AnsiEncoder {
+            coalesce,
+            color_depth,
+            line_width,
+            cursor: None,
+            foreground: None,
+            background: None,
+        }
+    }
+
+    /// Forget the cursor position and colors, needed after anything else has written to the terminal.
+    pub fn reset(&mut self) {
+        self.cursor = None;
+        self.foreground = None;
+        self.background = None;
+    }
+
+    /// Writes `cell` at `row` and `column` (1-based), repeating its glyph across `columns` columns.
+    pub fn write_cell<W: Write>(
+        &mut self,
+        out: &mut W,
+        row: usize,
+        column: usize,
+        cell: &Cell,
+        columns: usize,
+    ) -> std::io::Result<()> {
+        self.move_to(out, row, column)?;
+        self.set_colors(out, cell.foreground, cell.background)?;
+        for _ in 0..columns {
+            write!(out, "{}", cell.glyph)?;
+        }
+
+        // after the last column the cursor waits for the next glyph to wrap, which terminals handle differently
+        self.cursor = if column + columns <= self.line_width {
+            Some((row, column + columns))
+        } else {
+            None
+        };
+        Ok(())
+    }
+
+    /// Parks the cursor at the start of `row` and resets the colors, done at the end of every frame.
+    pub fn finish<W: Write>(&mut self, out: &mut W, row: usize) -> std::io::Result<()> {
+        write!(out, "\x1B[{};{}H\x1b[0m", row, 1)?;
+        self.cursor = Some((row, 1));
+        self.foreground = Some(None);
+        self.background = Some(None);
+        Ok(())
+    }
+
+    fn move_to<W: Write>(&mut self, out: &mut W, row: usize, column: usize) -> std::io::Result<()> {
+        match self.cursor {
+            Some(cursor) if self.coalesce && cursor == (row, column) => Ok(()),
+            Some((cursor_row, cursor_column)) if self.coalesce && cursor_row == row && cursor_column < column => {
+                match column - cursor_column {
+                    1 => write!(out, "\x1b[C"),
+                    distance => write!(out, "\x1b[{}C", distance),
+                }
+            }
+            _ => write!(out, "\x1B[{};{}H", row, column),
+        }
+    }
+
+    fn set_colors<W: Write>(
+        &mut self,
+        out: &mut W,
+        foreground: Option<Color>,
+        background: Option<Color>,
+    ) -> std::io::Result<()> {
+        let depth = self.color_depth;
+        if depth == ColorDepth::Monochrome {
+            return Ok(());
+        }
+
+        // colors that end up as the same palette entry do not need to be sent again
+        let foreground = foreground.map(|color| palette::quantize_color(color, depth));
+        let background = background.map(|color| palette::quantize_color(color, depth));
+
+        if !self.coalesce {
+            // background first, in the order frames were always written in
+            self.foreground = Some(foreground);
+            self.background = Some(background);
+            write!(out, "\x1b[")?;
+            palette::write_sgr_params(out, background, depth, true)?;
+            write!(out, "m\x1b[")?;
+            palette::write_sgr_params(out, foreground, depth, false)?;
+            return write!(out, "m");
+        }
+
+        let foreground_changed = self.foreground != Some(foreground);
+        let background_changed = self.background != Some(background);
+        if !foreground_changed && !background_changed {
+            return Ok(());
+        }
+
+        write!(out, "\x1b[")?;
+        if foreground_changed {
+            palette::write_sgr_params(out, foreground, depth, false)?;
+            self.foreground = Some(foreground);
+        }
+        if foreground_changed && background_changed {
+            write!(out, ";")?;
+        }
+        if background_changed {
+            palette::write_sgr_params(out, background, depth, true)?;
+            self.background = Some(background);
+        }
+        write!(out, "m")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cell(glyph: char, foreground: Color, background: Color) -> Cell {
+        Cell { glyph, foreground: Some(foreground), background: Some(background) }
+    }
+
+    const RED: Color = Color { r: 255, g: 0, b: 0 };
+    const BLUE: Color = Color { r: 0, g: 0, b: 255 };
+
+    /// Writes `cells` as `(row, column, cell)` with a fresh encoder and returns the output
+    fn encode(coalesce: bool, line_width: usize, cells: &[(usize, usize, Cell)]) -> String {
+        let mut encoder = AnsiEncoder::new(coalesce, ColorDepth::TrueColor, line_width);
+        let mut out = Vec::new();
+        for (row, column, cell) in cells {
+            encoder.write_cell(&mut out, *row, *column, cell, 1).unwrap();
+        }
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn plain_cells_get_a_position_and_both_colors() {
+        let red = cell('a', RED, BLUE);
+        assert_eq!(
+            encode(false, 80, &[(1, 1, red), (1, 2, red)]),
+            "\x1B[1;1H\x1b[48;2;0;0;255m\x1b[38;2;255;0;0ma\
+             \x1B[1;2H\x1b[48;2;0;0;255m\x1b[38;2;255;0;0ma"
+        );
+    }
+
+    #[test]
+    fn cursor_moves_are_relative_within_a_row() {
+        let red = cell('a', RED, BLUE);
+        assert_eq!(
+            encode(true, 80, &[(1, 1, red), (1, 2, red), (1, 4, red), (1, 9, red), (2, 1, red)]),
+            "\x1B[1;1H\x1b[38;2;255;0;0;48;2;0;0;255ma\
+             a\x1b[Ca\x1b[4Ca\
+             \x1B[2;1Ha"
+        );
+    }
+
+    #[test]
+    fn only_changed_colors_are_sent() {
+        assert_eq!(
+            encode(true, 80, &[(1, 1, cell('a', RED, BLUE)), (1, 2, cell('b', BLUE, BLUE)), (1, 3, cell('c', RED, RED))]),
+            "\x1B[1;1H\x1b[38;2;255;0;0;48;2;0;0;255ma\
+             \x1b[38;2;0;0;255mb\
+             \x1b[38;2;255;0;0;48;2;255;0;0mc"
+        );
+    }
+
+    #[test]
+    fn the_cursor_is_forgotten_at_the_end_of_a_line() {
+        // after the last column terminals differ on where the cursor is, so the next cell gets a position
+        let red = cell('a', RED, BLUE);
+        assert_eq!(
+            encode(true, 2, &[(1, 2, red), (2, 1, red)]),
+            "\x1B[1;2H\x1b[38;2;255;0;0;48;2;0;0;255ma\x1B[2;1Ha"
+        );
+        assert_eq!(encode(true, 3, &[(1, 2, red), (1, 3, red)]), "\x1B[1;2H\x1b[38;2;255;0;0;48;2;0;0;255maa");
+    }
+
+    #[test]
+    fn reset_forgets_the_terminal_state() {
+        let red = cell('a', RED, BLUE);
+        let mut encoder = AnsiEncoder::new(true, ColorDepth::TrueColor, 80);
+        let mut out = Vec::new();
+        encoder.write_cell(&mut out, 1, 1, &red, 1).unwrap();
+        encoder.reset();
+        out.clear();
+        encoder.write_cell(&mut out, 1, 2, &red, 1).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "\x1B[1;2H\x1b[38;2;255;0;0;48;2;0;0;255ma");
+    }
+}
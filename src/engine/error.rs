@@ -0,0 +1,85 @@
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+/// Everything that can go wrong while loading assets or starting the engine.
+#[derive(Debug)]
+pub enum EngineError {
+    /// A file could not be read or written
+    Io { path: PathBuf, source: std::io::Error },
+    /// An image could not be opened or decoded
+    Image { path: PathBuf, source: image::ImageError },
+    /// An .obj model could not be parsed or lacks the normals and texture coordinates the renderer needs
+    Obj { path: PathBuf, message: String },
+    /// A scene file is not valid JSON or does not match the scene schema
+    Scene { path: PathBuf, message: String },
+    /// A shader program could not be compiled or linked
+    Shader { vertex: PathBuf, fragment: PathBuf, message: String },
+    /// No OpenGL context or window could be created
+    Context(String),
+    /// The engine was started without a terminal on stdout to draw to
+    NotATerminal,
+    /// Writing to the terminal failed
+    Terminal(std::io::Error),
+}
+
+impl EngineError {
+    pub(crate) fn io(path: &Path, source: std::io::Error) -> EngineError {
+        EngineError::Io { path: path.to_path_buf(), source }
+    }
+
+    pub(crate) fn image(path: &Path, source: image::ImageError) -> EngineError {
+        EngineError::Image { path: path.to_path_buf(), source }
+    }
+
+    pub(crate) fn obj<M: ToString>(path: &Path, message: M) -> EngineError {
+        EngineError::Obj { path: path.to_path_buf(), message: message.to_string() }
+    }
+
+    pub(crate) fn scene<M: ToString>(path: &Path, message: M) -> EngineError {
+        EngineError::Scene { path: path.to_path_buf(), message: message.to_string() }
+    }
+
+    /// Path of the asset the error is about, if it is about one
+    pub fn path(&self) -> Option<&Path> {
+        match self {
+            EngineError::Io { path, .. }
+            | EngineError::Image { path, .. }
+            | EngineError::Obj { path, .. }
+            | EngineError::Scene { path, .. } => Some(path),
+            EngineError::Shader { vertex, .. } => Some(vertex),
+            EngineError::Context(_) | EngineError::NotATerminal | EngineError::Terminal(_) => None,
+        }
+    }
+}
+
+impl fmt::Display for EngineError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EngineError::Io { path, source } => write!(f, "failed to read {}: {}", path.display(), source),
+            EngineError::Image { path, source } => write!(f, "failed to load image {}: {}", path.display(), source),
+            EngineError::Obj { path, message } => write!(f, "failed to load model {}: {}", path.display(), message),
+            EngineError::Scene { path, message } => write!(f, "invalid scene {}: {}", path.display(), message),
+            EngineError::Shader { vertex, fragment, message } => write!(
+                f,
+                "failed to build shaders {} and {}: {}",
+                vertex.display(),
+                fragment.display(),
+                message
+            ),
+            EngineError::Context(message) => write!(f, "failed to create an OpenGL context: {}", message),
+            EngineError::NotATerminal => write!(f, "stdout is not a terminal"),
+            EngineError::Terminal(source) => write!(f, "failed to write to the terminal: {}", source),
+        }
+    }
+}
+
+impl std::error::Error for EngineError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            EngineError::Io { source, .. } => Some(source),
+            EngineError::Image { source, .. } => Some(source),
+            EngineError::Terminal(source) => Some(source),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,195 @@
+use super::ascii_render::{Cell, Color};
+use super::palette::{self, ColorDepth};
+use std::fmt::Write;
+
+/// Colors used for cells that keep the terminal's default colors
+const DEFAULT_FOREGROUND: Color = Color { r: 229, g: 229, b: 229 };
+const DEFAULT_BACKGROUND: Color = Color { r: 0, g: 0, b: 0 };
+
+/// Size of a terminal column in SVG units
+const SVG_COLUMN_WIDTH: usize = 8;
+const SVG_ROW_HEIGHT: usize = 16;
+
+/// Renders rows of `columns` cells as a self-contained HTML page.
+/// Every cell covers `columns_per_cell` terminal columns, colors are shown as they look at `color_depth`.
+pub fn html(cells: &[Cell], columns: usize, columns_per_cell: usize, color_depth: ColorDepth) -> String {
+    let mut page = String::new();
+    page.push_str("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>Frame</title>\n<style>\n");
+    let _ = writeln!(
+        page,
+        "body {{ margin: 0; background: {}; }}\npre {{ margin: 0; font-family: monospace; line-height: 1; color: {}; }}",
+        hex(DEFAULT_BACKGROUND),
+        hex(DEFAULT_FOREGROUND)
+    );
+    page.push_str("</style>\n</head>\n<body>\n<pre>");
+
+    for row in cells.chunks(columns.max(1)) {
+        // cells with the same colors share a span
+        let mut run: Option<(Color, Color)> = None;
+        for cell in row {
+            let colors = resolve(cell, color_depth);
+            if run != Some(colors) {
+                if run.is_some() {
+                    page.push_str("</span>");
+                }
+                let _ = write!(page, "<span style=\"color:{};background:{}\">", hex(colors.0), hex(colors.1));
+                run = Some(colors);
+            }
+            for _ in 0..columns_per_cell {
+                escape(&mut page, cell.glyph);
+            }
+        }
+        if run.is_some() {
+            page.push_str("</span>");
+        }
+        page.push('\n');
+    }
+
+    page.push_str("</pre>\n</body>\n</html>\n");
+    page
+}
+
+/// Renders rows of `columns` cells as an SVG document, every terminal column being 8 x 16 units.
+/// Every cell covers `columns_per_cell` terminal columns, colors are shown as they look at `color_depth`.
+pub fn svg(cells: &[Cell], columns: usize, columns_per_cell: usize, color_depth: ColorDepth) -> String {
+    let rows = cells.len().checked_div(columns).unwrap_or(0);
+    let cell_width = SVG_COLUMN_WIDTH * columns_per_cell;
+    let (width, height) = (columns * cell_width, rows * SVG_ROW_HEIGHT);
+
+    let mut document = String::new();
+    let _ = writeln!(
+        document,
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{width}\" height=\"{height}\" viewBox=\"0 0 {width} {height}\" \
+         font-family=\"monospace\" font-size=\"{}\" text-anchor=\"middle\">",
+        SVG_ROW_HEIGHT * 7 / 8
+    );
+    let _ = writeln!(
+        document,
+        "<rect width=\"{width}\" height=\"{height}\" fill=\"{}\"/>",
+        hex(DEFAULT_BACKGROUND)
+    );
+
+    for (y, row) in cells.chunks(columns.max(1)).enumerate() {
+        let top = y * SVG_ROW_HEIGHT;
+
+        // backgrounds, merged across cells of the same color
+        let mut start = 0;
+        while start < row.len() {
+            let background = resolve(&row[start], color_depth).1;
+            let end = start
+                + row[start..]
+                    .iter()
+                    .take_while(|cell| resolve(cell, color_depth).1 == background)
+                    .count();
+            let _ = writeln!(
+                document,
+                "<rect x=\"{}\" y=\"{top}\" width=\"{}\" height=\"{SVG_ROW_HEIGHT}\" fill=\"{}\"/>",
+                start * cell_width,
+                (end - start) * cell_width,
+                hex(background)
+            );
+            start = end;
+        }
+
+        for (x, cell) in row.iter().enumerate() {
+            let foreground = resolve(cell, color_depth).0;
+            let left = x * cell_width;
+            match cell.glyph {
+                ' ' | '\u{a0}' => {}
+                // fonts rarely fill the cell exactly, so the half block is drawn as a rectangle
+                '\u{2580}' => {
+                    let _ = writeln!(
+                        document,
+                        "<rect x=\"{left}\" y=\"{top}\" width=\"{cell_width}\" height=\"{}\" fill=\"{}\"/>",
+                        SVG_ROW_HEIGHT / 2,
+                        hex(foreground)
+                    );
+                }
+                glyph => {
+                    let mut text = String::new();
+                    for _ in 0..columns_per_cell {
+                        escape(&mut text, glyph);
+                    }
+                    let _ = writeln!(
+                        document,
+                        "<text x=\"{}\" y=\"{}\" fill=\"{}\">{}</text>",
+                        left + cell_width / 2,
+                        top + SVG_ROW_HEIGHT * 3 / 4,
+                        hex(foreground),
+                        text
+                    );
+                }
+            }
+        }
+    }
+
+    document.push_str("</svg>\n");
+    document
+}
+
+/// Foreground and background of `cell` as the terminal shows them at `color_depth`
+fn resolve(cell: &Cell, color_depth: ColorDepth) -> (Color, Color) {
+    let resolve = |color: Option<Color>, default: Color| match (color, color_depth) {
+        (_, ColorDepth::Monochrome) | (None, _) => default,
+        (Some(color), _) => palette::quantize_color(color, color_depth),
+    };
+    (
+        resolve(cell.foreground, DEFAULT_FOREGROUND),
+        resolve(cell.background, DEFAULT_BACKGROUND),
+    )
+}
+
+fn hex(color: Color) -> String {
+    format!("#{:02x}{:02x}{:02x}", color.r, color.g, color.b)
+}
+
+/// Appends `glyph` to `out`, escaped for HTML and XML
+fn escape(out: &mut String, glyph: char) {
+    match glyph {
+        '&' => out.push_str("&amp;"),
+        '<' => out.push_str("&lt;"),
+        '>' => out.push_str("&gt;"),
+        '"' => out.push_str("&quot;"),
+        glyph => out.push(glyph),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cell(glyph: char, foreground: Option<Color>, background: Option<Color>) -> Cell {
+        Cell { glyph, foreground, background }
+    }
+
+    #[test]
+    fn html_shares_spans_and_escapes_glyphs() {
+        let red = Some(Color { r: 255, g: 0, b: 0 });
+        let cells = [cell('<', red, None), cell('&', red, None), cell('a', None, red), cell('b', None, red)];
+        let page = html(&cells, 2, 2, ColorDepth::TrueColor);
+
+        assert!(page.contains(
+            "<pre><span style=\"color:#ff0000;background:#000000\">&lt;&lt;&amp;&amp;</span>\n\
+             <span style=\"color:#e5e5e5;background:#ff0000\">aabb</span>\n</pre>"
+        ));
+    }
+
+    #[test]
+    fn svg_draws_half_blocks_as_rectangles() {
+        let blue = Some(Color { r: 0, g: 0, b: 255 });
+        let cells = [cell('\u{2580}', blue, blue), cell('x', None, None)];
+        let document = svg(&cells, 2, 1, ColorDepth::TrueColor);
+
+        assert!(document.contains("<rect x=\"0\" y=\"0\" width=\"8\" height=\"16\" fill=\"#0000ff\"/>"));
+        assert!(document.contains("<rect x=\"0\" y=\"0\" width=\"8\" height=\"8\" fill=\"#0000ff\"/>"));
+        assert!(document.contains("<text x=\"12\" y=\"12\" fill=\"#e5e5e5\">x</text>"));
+    }
+
+    #[test]
+    fn colors_follow_the_color_depth() {
+        let cells = [cell('x', Some(Color { r: 250, g: 10, b: 10 }), None)];
+
+        assert!(html(&cells, 1, 1, ColorDepth::Ansi256).contains("color:#ff0000"));
+        assert!(html(&cells, 1, 1, ColorDepth::Monochrome).contains("color:#e5e5e5"));
+    }
+}
@@ -0,0 +1,332 @@
+use super::ascii_render::{CellMode, Color};
+use fontdue::Font;
+use serde_json::{json, Value};
+use std::path::Path;
+
+/// Glyphs used to show luminance, ordered from the darkest to the brightest.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GlyphRamp {
+    glyphs: Vec<char>,
+}
+
+impl GlyphRamp {
+    /// Creates a ramp from the characters of `glyphs`, darkest first. Returns `None` when it is empty.
+    pub fn new(glyphs: &str) -> Option<GlyphRamp> {
+        let glyphs: Vec<char> = glyphs.chars().collect();
+        if glyphs.is_empty() {
+            None
+        } else {
+            Some(GlyphRamp { glyphs })
+        }
+    }
+
+    /// Loads a ramp from a text file holding the glyphs on its first line, darkest first.
+    /// Spaces are kept as glyphs, only the line ending is removed.
+    pub fn load<P: AsRef<Path>>(path: P) -> std::io::Result<GlyphRamp> {
+        let contents = std::fs::read_to_string(path.as_ref())?;
+        let line = contents.lines().next().unwrap_or_default();
+        GlyphRamp::new(line).ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("glyph ramp {} is empty", path.as_ref().display()),
+            )
+        })
+    }
+
+    /// The glyphs of the ramp, darkest first
+    pub fn glyphs(&self) -> &[char] {
+        &self.glyphs
+    }
+
+    /// Returns the glyph for a luminance in the 0.0 - 1.0 range
+    pub fn glyph(&self, luminance: f32) -> char {
+        self.glyphs[self.index(luminance)]
+    }
+
+    /// Returns the index into `glyphs` for a luminance in the 0.0 - 1.0 range
+    pub fn index(&self, luminance: f32) -> usize {
+        let last = self.glyphs.len() - 1;
+        last - ((1.0 - luminance.clamp(0.0, 1.0)) * last as f32) as usize
+    }
+}
+
+impl Default for GlyphRamp {
+    fn default() -> Self {
+        GlyphRamp::new("\u{a0}.,:;+*?%S#@").unwrap()
+    }
+}
+
+/// How the luminance of a color is measured when picking glyphs and Braille dots.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Default)]
+pub enum LuminanceMetric {
+    /// Luma with the Rec. 709 (sRGB / HDTV) weights
+    Rec709,
+    /// Luma with the Rec. 601 (SDTV) weights
+    Rec601,
+    /// Lightness of the HSL color model, the average of the largest and smallest channel
+    #[default]
+    HslLightness,
+    /// CIELAB L*, scaled to 0.0 - 1.0
+    CieLab,
+}
+
+impl LuminanceMetric {
+    /// Returns the luminance of `color` in the 0.0 - 1.0 range
+    pub fn luminance(&self, color: Color) -> f32 {
+        let r = f32::from(color.r) / 255.0;
+        let g = f32::from(color.g) / 255.0;
+        let b = f32::from(color.b) / 255.0;
+
+        match self {
+            LuminanceMetric::Rec709 => 0.2126 * r + 0.7152 * g + 0.0722 * b,
+            LuminanceMetric::Rec601 => 0.299 * r + 0.587 * g + 0.114 * b,
+            LuminanceMetric::HslLightness => (r.max(g.max(b)) + r.min(g.min(b))) / 2.0,
+            LuminanceMetric::CieLab => {
+                let y = 0.2126 * srgb_to_linear(r) + 0.7152 * srgb_to_linear(g) + 0.0722 * srgb_to_linear(b);
+                let f = if y > 216.0 / 24389.0 {
+                    y.cbrt()
+                } else {
+                    (24389.0 / 27.0 * y + 16.0) / 116.0
+                };
+                (116.0 * f - 16.0) / 100.0
+            }
+        }
+    }
+}
+
+/// Coverage masks of glyphs rasterized from a font, used by `CellMode::Shape` to pick the glyph
+/// whose shape matches the pixels of a cell best.
+/// Every mask has the size of a `CellMode::Shape` cell and holds how much of each pixel the glyph covers,
+/// scaled so the most covered pixel is 1.0.
+#[derive(Clone, Debug, PartialEq)]
+pub struct GlyphTable {
+    glyphs: Vec<(char, Vec<f32>)>,
+}
+
+impl GlyphTable {
+    /// The printable ASCII characters, a good default for `GlyphTable::build`
+    pub const PRINTABLE_ASCII: &'static str =
+        " !\"#$%&'()*+,-./0123456789:;<=>?@ABCDEFGHIJKLMNOPQRSTUVWXYZ[\\]^_`abcdefghijklmnopqrstuvwxyz{|}~";
+
+    /// Rasterizes `glyphs` with `font` into a table.
+    /// Glyphs are centered in a cell that is twice as high as it is wide, like a terminal cell.
+    pub fn build(font: &Font, glyphs: &str) -> GlyphTable {
+        let (cell_width, cell_height) = CellMode::Shape.cell_size();
+        let px = 64.0;
+
+        let line_metrics = font.horizontal_line_metrics(px);
+        let (ascent, descent) = line_metrics.map_or((px, 0.0), |metrics| (metrics.ascent, metrics.descent));
+        let canvas_height = (ascent - descent).ceil() as usize;
+        let canvas_width = canvas_height / 2;
+
+        let glyphs = glyphs
+            .chars()
+            .map(|glyph| {
+                // draw the glyph onto a canvas the size of a cell, baseline at `ascent`
+                let (metrics, bitmap) = font.rasterize(glyph, px);
+                let mut canvas = vec![0.0f32; canvas_width * canvas_height];
+                let left = metrics.xmin + ((canvas_width as f32 - metrics.advance_width) / 2.0) as i32;
+                let top = ascent as i32 - (metrics.ymin + metrics.height as i32);
+                for y in 0..metrics.height {
+                    for x in 0..metrics.width {
+                        let canvas_x = left + x as i32;
+                        let canvas_y = top + y as i32;
+                        if canvas_x >= 0
+                            && canvas_y >= 0
+                            && (canvas_x as usize) < canvas_width
+                            && (canvas_y as usize) < canvas_height
+                        {
+                            canvas[canvas_y as usize * canvas_width + canvas_x as usize] =
+                                f32::from(bitmap[y * metrics.width + x]) / 255.0;
+                        }
+                    }
+                }
+
+                // average the canvas down to the cell size
+                let mut coverage = vec![0.0f32; cell_width * cell_height];
+                for (i, value) in coverage.iter_mut().enumerate() {
+                    let (x, y) = (i % cell_width, i / cell_width);
+                    let (x0, x1) = (x * canvas_width / cell_width, (x + 1) * canvas_width / cell_width);
+                    let (y0, y1) = (y * canvas_height / cell_height, (y + 1) * canvas_height / cell_height);
+                    let mut sum = 0.0;
+                    for canvas_y in y0..y1 {
+                        for canvas_x in x0..x1 {
+                            sum += canvas[canvas_y * canvas_width + canvas_x];
+                        }
+                    }
+                    *value = sum / ((x1 - x0) * (y1 - y0)).max(1) as f32;
+                }
+
+                // strokes rarely fill a whole pixel, scale them up so shapes are compared instead of amounts of ink
+                let peak = coverage.iter().copied().fold(0.0, f32::max);
+                if peak > 0.0 {
+                    for value in coverage.iter_mut() {
+                        *value /= peak;
+                    }
+                }
+                (glyph, coverage)
+            })
+            .collect();
+
+        GlyphTable { glyphs }
+    }
+
+    /// Loads a table saved with `GlyphTable::save`
+    pub fn load<P: AsRef<Path>>(path: P) -> std::io::Result<GlyphTable> {
+        let invalid = |message: &str| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("glyph table {}: {}", path.as_ref().display(), message),
+            )
+        };
+
+        let json = std::fs::read_to_string(path.as_ref())?;
+        let v: Value = serde_json::from_str(json.as_str()).map_err(|error| invalid(&error.to_string()))?;
+
+        let (cell_width, cell_height) = CellMode::Shape.cell_size();
+        if v.get("cell_width").and_then(Value::as_u64) != Some(cell_width as u64)
+            || v.get("cell_height").and_then(Value::as_u64) != Some(cell_height as u64)
+        {
+            return Err(invalid("cell size does not match CellMode::Shape"));
+        }
+
+        let mut glyphs = Vec::new();
+        for entry in v.get("glyphs").and_then(Value::as_array).ok_or_else(|| invalid("missing glyphs"))? {
+            let glyph = entry
+                .get(0)
+                .and_then(Value::as_str)
+                .and_then(|glyph| glyph.chars().next())
+                .ok_or_else(|| invalid("missing glyph"))?;
+            let coverage: Vec<f32> = entry
+                .get(1)
+                .and_then(Value::as_array)
+                .ok_or_else(|| invalid("missing coverage"))?
+                .iter()
+                .filter_map(Value::as_f64)
+                .map(|value| value as f32)
+                .collect();
+            if coverage.len() != cell_width * cell_height {
+                return Err(invalid("coverage does not match the cell size"));
+            }
+            glyphs.push((glyph, coverage));
+        }
+
+        Ok(GlyphTable { glyphs })
+    }
+
+    /// Saves the table as JSON so it does not have to be rasterized again
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> std::io::Result<()> {
+        let (cell_width, cell_height) = CellMode::Shape.cell_size();
+        let glyphs: Vec<Value> = self
+            .glyphs
+            .iter()
+            .map(|(glyph, coverage)| {
+                // rounded as f64, an f32 like 0.437 would be written as 0.43700000643730164
+                let coverage: Vec<f64> =
+                    coverage.iter().map(|value| (f64::from(*value) * 1000.0).round() / 1000.0).collect();
+                json!([glyph.to_string(), coverage])
+            })
+            .collect();
+        let v = json!({
+            "cell_width": cell_width,
+            "cell_height": cell_height,
+            "glyphs": glyphs,
+        });
+        std::fs::write(path, v.to_string())
+    }
+
+    /// Loads the table from `path`, or builds it and saves it there when it can not be loaded
+    pub fn load_or_build<P: AsRef<Path>>(path: P, font: &Font, glyphs: &str) -> std::io::Result<GlyphTable> {
+        match GlyphTable::load(path.as_ref()) {
+            Ok(table) => Ok(table),
+            Err(_) => {
+                let table = GlyphTable::build(font, glyphs);
+                table.save(path)?;
+                Ok(table)
+            }
+        }
+    }
+
+    /// The glyphs in the table with their coverage masks
+    pub fn glyphs(&self) -> &[(char, Vec<f32>)] {
+        &self.glyphs
+    }
+
+    /// Returns the glyph whose coverage is closest to `coverage` and the squared error between them
+    pub fn closest(&self, coverage: &[f32]) -> Option<(char, f32)> {
+        self.glyphs
+            .iter()
+            .map(|(glyph, mask)| {
+                let error = mask
+                    .iter()
+                    .zip(coverage)
+                    .map(|(mask, coverage)| (mask - coverage) * (mask - coverage))
+                    .sum::<f32>();
+                (*glyph, error)
+            })
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+    }
+}
+
+fn srgb_to_linear(value: f32) -> f32 {
+    if value <= 0.04045 {
+        value / 12.92
+    } else {
+        ((value + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Mask of a Shape cell with the pixels of `rows` (0 - 7) fully covered
+    fn rows(rows: std::ops::Range<usize>) -> Vec<f32> {
+        (0..4 * 8).map(|i| if rows.contains(&(i / 4)) { 1.0 } else { 0.0 }).collect()
+    }
+
+    fn table() -> GlyphTable {
+        GlyphTable {
+            glyphs: vec![
+                (' ', rows(0..0)),
+                ('_', rows(7..8)),
+                ('\u{2580}', rows(0..4)),
+                ('#', (0..32).map(|i| (i as f32 * 0.0371) % 1.0).collect()),
+            ],
+        }
+    }
+
+    #[test]
+    fn closest_picks_the_most_similar_mask() {
+        let table = table();
+        assert_eq!(table.closest(&rows(0..4)), Some(('\u{2580}', 0.0)));
+        assert_eq!(table.closest(&rows(6..8)).map(|(glyph, _)| glyph), Some('_'));
+        assert_eq!(table.closest(&rows(0..1)).map(|(glyph, _)| glyph), Some(' '));
+
+        let (glyph, error) = table.closest(&rows(0..3)).unwrap();
+        assert_eq!(glyph, '\u{2580}');
+        assert!((error - 4.0).abs() < 1e-6);
+
+        assert_eq!(GlyphTable { glyphs: Vec::new() }.closest(&rows(0..4)), None);
+    }
+
+    #[test]
+    fn saved_tables_load_back_rounded_to_three_decimals() {
+        let path = std::env::temp_dir().join(format!("ascii_glyph_table_{}.json", std::process::id()));
+        let table = table();
+        table.save(&path).unwrap();
+        let json = std::fs::read_to_string(&path).unwrap();
+        let loaded = GlyphTable::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(json.contains("0.037,"), "{}", json);
+        assert!(!json.contains("0000"), "{}", json);
+        assert_eq!(loaded.glyphs().len(), table.glyphs().len());
+        for ((glyph, mask), (loaded_glyph, loaded_mask)) in table.glyphs().iter().zip(loaded.glyphs()) {
+            assert_eq!(glyph, loaded_glyph);
+            for (value, loaded_value) in mask.iter().zip(loaded_mask) {
+                assert!((value - loaded_value).abs() <= 0.0005, "{} {}", value, loaded_value);
+            }
+        }
+    }
+}
@@ -0,0 +1,177 @@
+use super::ascii_render::{Cell, CellMode, Color, TerminalFrameBuffer};
+use super::camera::Camera;
+use super::core::Game;
+use super::error::EngineError;
+use super::object::{Object, Upload};
+use super::render::{self, GlRenderer, RenderSettings, Renderer};
+use super::software::SoftwareRenderer;
+use glium::glutin;
+
+/// One frame rendered by `HeadlessRenderer`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct HeadlessFrame {
+    /// Size of the frame in terminal columns and rows
+    pub columns: usize,
+    pub rows: usize,
+    /// Cells row by row, every cell covers `OutputSettings::cell_mode.columns_per_cell()` columns
+    pub cells: Vec<Cell>,
+    /// Size of the rendered RGBA image in pixels
+    pub pixel_width: u32,
+    pub pixel_height: u32,
+    /// Rendered RGBA image, bottom row first
+    pub pixels: Vec<u8>,
+}
+
+enum Backend {
+    Gl(Box<GlRenderer<glium::HeadlessRenderer>>),
+    Software(SoftwareRenderer),
+}
+
+/// Renders games into memory without stdout, a terminal or a window, for snapshot tests.
+/// Scenes, objects and UI elements have to be created with the renderer as their `Upload`.
+pub struct HeadlessRenderer {
+    backend: Backend,
+    /// Only kept when no OSMesa context could be created and the context belongs to a hidden event loop
+    _event_loop: Option<glutin::event_loop::EventLoop<()>>,
+}
+
+impl HeadlessRenderer {
+    /// Creates an OpenGL context without a window and loads the shaders from `{assets_path}/shaders`,
+    /// falling back to `HeadlessRenderer::software` when no context can be created.
+    pub fn new(assets_path: &str) -> HeadlessRenderer {
+        HeadlessRenderer::with_settings(assets_path, &RenderSettings::default())
+    }
+
+    /// Like `HeadlessRenderer::new`, drawing with `settings`
+    pub fn with_settings(assets_path: &str, settings: &RenderSettings) -> HeadlessRenderer {
+        HeadlessRenderer::opengl(assets_path, settings).unwrap_or_else(|_| HeadlessRenderer::software(settings))
+    }
+
+    /// Creates an OpenGL context without a window and loads the shaders of `settings` from `assets_path`.
+    /// On Linux and the BSDs OSMesa is tried first, so no display server is needed.
+    pub fn opengl(assets_path: &str, settings: &RenderSettings) -> Result<HeadlessRenderer, EngineError> {
+        let (context, event_loop) = match HeadlessRenderer::osmesa_context() {
+            Ok(context) => (context, None),
+            // winit panics instead of failing when it can not reach a display server
+            Err(error) if !HeadlessRenderer::has_display() => {
+                return Err(EngineError::Context(format!(
+                    "no OSMesa ({}) and no display server to fall back to",
+                    error
+                )));
+            }
+            Err(_) => {
+                let event_loop = glutin::event_loop::EventLoop::new();
+                let context = glutin::ContextBuilder::new()
+                    .with_depth_buffer(24)
+                    .build_headless(&event_loop, glutin::dpi::PhysicalSize::new(1, 1))
+                    .map_err(|error| EngineError::Context(error.to_string()))?;
+                (context, Some(event_loop))
+            }
+        };
+
+        let facade =
+            glium::HeadlessRenderer::new(context).map_err(|error| EngineError::Context(error.to_string()))?;
+        let renderer = GlRenderer::with_settings(facade, assets_path, settings)?;
+
+        Ok(HeadlessRenderer {
+            backend: Backend::Gl(Box::new(renderer)),
+            _event_loop: event_loop,
+        })
+    }
+
+    /// Renders on the CPU with `SoftwareRenderer`, works everywhere
+    pub fn software(settings: &RenderSettings) -> HeadlessRenderer {
+        HeadlessRenderer {
+            backend: Backend::Software(SoftwareRenderer::with_settings(settings)),
+            _event_loop: None,
+        }
+    }
+
+    /// Checks if frames are rendered with OpenGL rather than on the CPU
+    pub fn is_opengl(&self) -> bool {
+        matches!(self.backend, Backend::Gl(_))
+    }
+
+    /// Drops the offscreen targets of the OpenGL backend, see `GlRenderer::release_targets`
+    pub fn release_targets(&mut self) {
+        if let Backend::Gl(renderer) = &mut self.backend {
+            renderer.release_targets();
+        }
+    }
+
+    #[cfg(any(
+        target_os = "linux",
+        target_os = "dragonfly",
+        target_os = "freebsd",
+        target_os = "netbsd",
+        target_os = "openbsd",
+    ))]
+    fn osmesa_context() -> Result<glutin::Context<glutin::NotCurrent>, glutin::CreationError> {
+        use glutin::platform::unix::HeadlessContextExt;
+
+        glutin::ContextBuilder::new()
+            .with_depth_buffer(24)
+            .build_osmesa(glutin::dpi::PhysicalSize::new(1, 1))
+    }
+
+    #[cfg(not(any(
+        target_os = "linux",
+        target_os = "dragonfly",
+        target_os = "freebsd",
+        target_os = "netbsd",
+        target_os = "openbsd",
+    )))]
+    fn osmesa_context() -> Result<glutin::Context<glutin::NotCurrent>, glutin::CreationError> {
+        Err(glutin::CreationError::NotSupported("OSMesa is not available on this platform".to_string()))
+    }
+
+    /// Checks if a display server is configured for the event loop fallback
+    pub(crate) fn has_display() -> bool {
+        if cfg!(any(
+            target_os = "linux",
+            target_os = "dragonfly",
+            target_os = "freebsd",
+            target_os = "netbsd",
+            target_os = "openbsd",
+        )) {
+            std::env::var_os("DISPLAY").is_some() || std::env::var_os("WAYLAND_DISPLAY").is_some()
+        } else {
+            true
+        }
+    }
+
+    /// Renders the current scene and UI of `game` as seen from `camera` on `columns` x `rows` terminal cells,
+    /// using the output settings of the game.
+    pub fn render(&mut self, game: &Game, camera: &Camera, columns: usize, rows: usize) -> HeadlessFrame {
+        // the framebuffer starts out in the default cell mode and is resized when the settings are applied
+        let (width, height) = CellMode::default().pixel_size(columns, rows);
+        let mut terminal_fb =
+            TerminalFrameBuffer::with_writer(width, height, Color { r: 0, g: 0, b: 0 }, std::io::sink());
+        terminal_fb.set_settings(game.output.clone());
+
+        let renderer: &mut dyn Renderer = match &mut self.backend {
+            Backend::Gl(renderer) => renderer.as_mut(),
+            Backend::Software(renderer) => renderer,
+        };
+        let pixels = render::render_to_framebuffer(renderer, game, camera, &mut terminal_fb);
+        terminal_fb.draw_frame().expect("writing to a sink does not fail");
+
+        HeadlessFrame {
+            columns,
+            rows,
+            cells: terminal_fb.front_cells(),
+            pixel_width: pixels.width,
+            pixel_height: pixels.height,
+            pixels: pixels.data,
+        }
+    }
+}
+
+impl Upload for HeadlessRenderer {
+    fn upload(&self, object: &mut Object) -> Result<(), EngineError> {
+        match &self.backend {
+            Backend::Gl(renderer) => renderer.get_facade().upload(object),
+            Backend::Software(_) => Ok(()),
+        }
+    }
+}
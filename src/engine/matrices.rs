@@ -142,16 +142,22 @@ pub fn model_matrix(position: &[f32; 3], rotation: &[f32; 3], scale: &[f32; 3])
     m
 }
 
+/// Distance of the near clipping plane of `perspective_matrix`
+pub const ZNEAR: f32 = 0.1;
+/// Distance of the far clipping plane of `perspective_matrix`
+pub const ZFAR: f32 = 1024.0;
+
 /// Returns a perspective matrix for the given position and rotation.
 pub fn perspective_matrix(terminal_size: (u32, u32)) -> [[f32; 4]; 4] {
     let (width, height) = terminal_size;
     let width = width as u16;
     let height = height as u16;
-    let aspect_ratio = f32::from(height) / f32::from(width);
+    // terminal cells are about twice as tall as they are wide
+    let aspect_ratio = 2.0 * f32::from(height) / f32::from(width);
 
     let fov: f32 = std::f32::consts::PI / 3.0;
-    let zfar = 1024.0;
-    let znear = 0.1;
+    let zfar = ZFAR;
+    let znear = ZNEAR;
 
     let f = 1.0 / (fov / 2.0).tan();
 
@@ -1,7 +1,19 @@
 pub mod ascii_render;
 pub mod camera;
 pub mod core;
+pub mod edges;
+pub mod encoder;
+pub mod error;
+pub mod export;
+pub mod glyph;
+pub mod headless;
 pub mod matrices;
 pub mod object;
+pub mod palette;
+pub mod recorder;
+pub mod render;
 pub mod scene;
+pub mod signal;
+pub mod software;
+pub mod terminal;
 pub mod ui;
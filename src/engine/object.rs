@@ -1,8 +1,11 @@
 //WIP
 
 
+use super::error::EngineError;
+use glium::index::PrimitiveType;
 use glium::texture::RawImage2d;
 use glium::{ self, backend::Facade, Texture2d };
+use image::RgbaImage;
 use std::path::Path;
 use tobj;
 
@@ -15,7 +18,16 @@ pub struct Vertex {
 
 implement_vertex!(Vertex, position, normal, tex_coords);
 
-#[derive(Debug)]
+/// Opens the image at `path` as RGBA, top row first
+pub fn load_image(path: &Path) -> Result<RgbaImage, EngineError> {
+    match image::open(path) {
+        Ok(image) => Ok(image.to_rgba8()),
+        Err(image::ImageError::IoError(source)) => Err(EngineError::io(path, source)),
+        Err(error) => Err(EngineError::image(path, error)),
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub enum TextureFilter {
     Nearest,
     Linear,
@@ -27,15 +39,74 @@ pub struct AABB {
     pub max: [f32; 3],
 }
 
+/// Buffers and texture of an object on the GPU, drawn by `GlRenderer`
+#[derive(Debug)]
+pub struct GpuObject {
+    pub vb: glium::VertexBuffer<Vertex>,
+    pub ib: glium::IndexBuffer<u32>,
+    pub texture: Texture2d,
+}
+
+impl GpuObject {
+    /// Uploads the mesh and texture of `object` with `facade`, failing with `EngineError::Context` if the driver can not create them
+    pub fn new(facade: &impl Facade, object: &Object) -> Result<GpuObject, EngineError> {
+        let texture = match &object.image {
+            Some(image) => {
+                let image_dimensions = image.dimensions();
+                let image = RawImage2d::from_raw_rgba_reversed(image.as_raw(), image_dimensions);
+                Texture2d::new(facade, image)
+            }
+            //blank white texture
+            None => Texture2d::new(facade, RawImage2d::from_raw_rgba(vec![255u8; 4], (1, 1))),
+        }
+        .map_err(|error| EngineError::Context(error.to_string()))?;
+
+        Ok(GpuObject {
+            vb: glium::VertexBuffer::new(facade, &object.vertices)
+                .map_err(|error| EngineError::Context(error.to_string()))?,
+            ib: glium::IndexBuffer::new(facade, object.primitive, &object.indices)
+                .map_err(|error| EngineError::Context(error.to_string()))?,
+            texture,
+        })
+    }
+}
+
+/// Where objects are uploaded to when they are created.
+/// Every glium facade uploads them to the GPU, `CpuOnly` keeps them in memory for `SoftwareRenderer`.
+pub trait Upload {
+    fn upload(&self, object: &mut Object) -> Result<(), EngineError>;
+}
+
+impl<F: Facade> Upload for F {
+    fn upload(&self, object: &mut Object) -> Result<(), EngineError> {
+        object.gpu = Some(GpuObject::new(self, object)?);
+        Ok(())
+    }
+}
+
+/// Keeps objects in memory only, for running without an OpenGL driver
+#[derive(Copy, Clone, Debug, Default)]
+pub struct CpuOnly;
+
+impl Upload for CpuOnly {
+    fn upload(&self, _object: &mut Object) -> Result<(), EngineError> {
+        Ok(())
+    }
+}
+
 #[derive(Debug)]
 pub struct Object {
     // pub name: String,
     pub model: [[f32; 4]; 4],
-    pub texture: Texture2d,
+    /// Texture, top row first. `None` for a plain white texture
+    pub image: Option<RgbaImage>,
     pub texture_filter: TextureFilter,
-    pub vb: glium::VertexBuffer<Vertex>,
     pub vertices: Vec<Vertex>,
-    pub ib: glium::IndexBuffer<u32>,
+    pub indices: Vec<u32>,
+    /// How `indices` form triangles
+    pub primitive: PrimitiveType,
+    /// Buffers and texture on the GPU, `None` until the object is uploaded
+    pub gpu: Option<GpuObject>,
     pub tags: Vec<String>,
 }
 
@@ -47,26 +118,44 @@ impl Object {
         texture_path: Option<&str>,
         texture_filter: TextureFilter,
         model: [[f32; 4]; 4],
-        display: &glium::Display,
+        display: &(impl Upload + ?Sized),
         tags: Vec<String>,
-    ) -> Object {
-
-        // println!("Texture path: {:?}", texture_path);
-
-
-        let facade = display.get_context();
+    ) -> Result<Object, EngineError> {
+        let mut object = Object::load(file_path, texture_path, texture_filter, model, tags)?;
+        display.upload(&mut object)?;
+        Ok(object)
+    }
 
+    /// Loads an .obj file and its texture into memory without uploading them
+    pub fn load(
+        file_path: &str,
+        texture_path: Option<&str>,
+        texture_filter: TextureFilter,
+        model: [[f32; 4]; 4],
+        tags: Vec<String>,
+    ) -> Result<Object, EngineError> {
         let load_options = tobj::LoadOptions {
             single_index: true,
             triangulate: true,
             ..Default::default()
         };
-        let (models, _materials) = tobj::load_obj(file_path, &load_options).unwrap();
+        let path = Path::new(file_path);
+        // tobj does not tell why a file could not be opened
+        std::fs::metadata(path).map_err(|source| EngineError::io(path, source))?;
+        let (models, _materials) =
+            tobj::load_obj(path, &load_options).map_err(|error| EngineError::obj(path, error))?;
         let mut verticies = Vec::new();
         let mut indicies = Vec::new();
 
         for m in models {
             let mesh = m.mesh;
+            let vertex_count = mesh.positions.len() / 3;
+            if mesh.normals.len() < vertex_count * 3 {
+                return Err(EngineError::obj(path, format!("mesh {} has no normals", m.name)));
+            }
+            if mesh.texcoords.len() < vertex_count * 2 {
+                return Err(EngineError::obj(path, format!("mesh {} has no texture coordinates", m.name)));
+            }
             for i in 0..mesh.positions.len() / 3 {
                 let vertex = Vertex {
                     position: (
@@ -82,54 +171,32 @@ impl Object {
             indicies = mesh.indices;
         }
 
-        let texture_val: Texture2d;
-
-        if let Some(texture_path) = texture_path {
-            let texture_path = texture_path.to_string();
-            let image = image::open(Path::new(&texture_path)).unwrap().to_rgba8();
-            let image_dimensions = image.dimensions();
-            let image = RawImage2d::from_raw_rgba_reversed(&image.into_raw(), image_dimensions);
-            texture_val = Texture2d::new(display, image).unwrap();
-        } else {
-            //create blank texture
-
-            texture_val = Texture2d::empty(display, 512, 512).unwrap();
-            //fill texture with white
-            let texture_data = vec![255u8; 512 * 512 * 4];
-
-            texture_val.write(
-                glium::Rect {
-                    left: 0,
-                    bottom: 0,
-                    width: 512,
-                    height: 512,
-                },
-                glium::texture::RawImage2d {
-                    data: std::borrow::Cow::Borrowed(&texture_data),
-                    width: 512,
-                    height: 512,
-                    format: glium::texture::ClientFormat::U8U8U8U8,
-                }
-            );
-        }
-
-        // println!("verticies: {:?}", verticies);
-        // println!("indicies: {:?}", indicies);
-
-        let vb = glium::VertexBuffer::new(facade, &verticies).unwrap();
-        let ib = glium::IndexBuffer
-            ::new(facade, glium::index::PrimitiveType::TrianglesList, &indicies)
-            .unwrap();
+        let image = match texture_path {
+            Some(texture_path) => Some(load_image(Path::new(texture_path))?),
+            None => None,
+        };
 
+        Ok(Object::from_mesh(verticies, indicies, PrimitiveType::TrianglesList, image, texture_filter, model, tags))
+    }
 
+    /// Creates an object from a mesh and texture in memory without uploading them
+    pub fn from_mesh(
+        vertices: Vec<Vertex>,
+        indices: Vec<u32>,
+        primitive: PrimitiveType,
+        image: Option<RgbaImage>,
+        texture_filter: TextureFilter,
+        model: [[f32; 4]; 4],
+        tags: Vec<String>,
+    ) -> Object {
         Object {
-            // name,
             model,
-            texture: texture_val,
+            image,
             texture_filter,
-            vb,
-            vertices: verticies,
-            ib,
+            vertices,
+            indices,
+            primitive,
+            gpu: None,
             tags,
         }
     }
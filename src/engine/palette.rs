@@ -0,0 +1,362 @@
+use super::ascii_render::Color;
+use std::io::Write;
+
+/// Number of colors the terminal can display.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Default)]
+pub enum ColorDepth {
+    /// 24-bit colors (`38;2;r;g;b`)
+    #[default]
+    TrueColor,
+    /// xterm 256 color palette, using the 6x6x6 cube and the greyscale ramp (`38;5;n`)
+    Ansi256,
+    /// The 16 base colors (`30-37` and `90-97`)
+    Ansi16,
+    /// No color sequences at all, the image is carried by the glyphs alone
+    Monochrome,
+}
+
+/// Dithering applied when quantizing to a limited palette.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Default)]
+pub enum Dither {
+    /// Use the nearest palette color
+    #[default]
+    None,
+    /// 4x4 ordered (Bayer) dithering
+    Ordered,
+    /// Floyd–Steinberg error diffusion
+    FloydSteinberg,
+}
+
+/// Levels of each channel in the xterm 6x6x6 color cube.
+const CUBE_LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+
+/// Default xterm values of the 16 base colors.
+const BASE_COLORS: [(u8, u8, u8); 16] = [
+    (0, 0, 0),
+    (205, 0, 0),
+    (0, 205, 0),
+    (205, 205, 0),
+    (0, 0, 238),
+    (205, 0, 205),
+    (0, 205, 205),
+    (229, 229, 229),
+    (127, 127, 127),
+    (255, 0, 0),
+    (0, 255, 0),
+    (255, 255, 0),
+    (92, 92, 255),
+    (255, 0, 255),
+    (0, 255, 255),
+    (255, 255, 255),
+];
+
+/// 4x4 Bayer matrix, indexed as `[y][x]`.
+const BAYER_4X4: [[u8; 4]; 4] = [[0, 8, 2, 10], [12, 4, 14, 6], [3, 11, 1, 9], [15, 7, 13, 5]];
+
+impl ColorDepth {
+    /// Rough distance between neighbouring palette colors, used to scale ordered dithering.
+    fn spread(&self) -> f32 {
+        match self {
+            ColorDepth::TrueColor | ColorDepth::Monochrome => 0.0,
+            ColorDepth::Ansi256 => 40.0,
+            ColorDepth::Ansi16 => 128.0,
+        }
+    }
+}
+
+/// Returns the RGB value of a palette index (0 - 255) as displayed by xterm.
+pub fn palette_color(index: u8) -> Color {
+    match index {
+        0..=15 => {
+            let (r, g, b) = BASE_COLORS[index as usize];
+            Color { r, g, b }
+        }
+        16..=231 => {
+            let index = index - 16;
+            Color {
+                r: CUBE_LEVELS[(index / 36) as usize],
+                g: CUBE_LEVELS[((index / 6) % 6) as usize],
+                b: CUBE_LEVELS[(index % 6) as usize],
+            }
+        }
+        _ => {
+            let level = 8 + (index - 232) * 10;
+            Color { r: level, g: level, b: level }
+        }
+    }
+}
+
+/// Returns the palette index closest to `color`.
+/// `ColorDepth::Ansi256` picks from the cube and greyscale ramp (16 - 255), `ColorDepth::Ansi16` from 0 - 15.
+/// `ColorDepth::TrueColor` and `ColorDepth::Monochrome` have no palette and are treated like `ColorDepth::Ansi256`.
+pub fn quantize(color: Color, depth: ColorDepth) -> u8 {
+    match depth {
+        ColorDepth::Ansi16 => nearest(color, 0..=15),
+        ColorDepth::TrueColor | ColorDepth::Monochrome | ColorDepth::Ansi256 => {
+            let cube = 16
+                + 36 * nearest_cube_level(color.r)
+                + 6 * nearest_cube_level(color.g)
+                + nearest_cube_level(color.b);
+            let average = (u32::from(color.r) + u32::from(color.g) + u32::from(color.b)) / 3;
+            let grey = 232 + (average.saturating_sub(3) / 10).min(23) as u8;
+
+            if distance(color, palette_color(grey)) < distance(color, palette_color(cube)) {
+                grey
+            } else {
+                cube
+            }
+        }
+    }
+}
+
+/// Returns the color that is displayed for `color` at the given depth.
+pub fn quantize_color(color: Color, depth: ColorDepth) -> Color {
+    match depth {
+        ColorDepth::TrueColor | ColorDepth::Monochrome => color,
+        _ => palette_color(quantize(color, depth)),
+    }
+}
+
+/// Quantizes a buffer of packed `0xRRGGBB` pixels in place, so every pixel is an exact palette color.
+pub fn dither_buffer(pixels: &mut [u32], width: usize, height: usize, depth: ColorDepth, dither: Dither) {
+    if depth == ColorDepth::TrueColor || depth == ColorDepth::Monochrome {
+        return;
+    }
+
+    match dither {
+        Dither::None => {
+            for pixel in pixels.iter_mut() {
+                *pixel = u32::from(quantize_color(Color::from(*pixel), depth));
+            }
+        }
+        Dither::Ordered => {
+            let spread = depth.spread();
+            for y in 0..height {
+                for x in 0..width {
+                    let offset = ((f32::from(BAYER_4X4[y % 4][x % 4]) + 0.5) / 16.0 - 0.5) * spread;
+                    let color = Color::from(pixels[y * width + x]);
+                    let color = Color {
+                        r: (f32::from(color.r) + offset).clamp(0.0, 255.0) as u8,
+                        g: (f32::from(color.g) + offset).clamp(0.0, 255.0) as u8,
+                        b: (f32::from(color.b) + offset).clamp(0.0, 255.0) as u8,
+                    };
+                    pixels[y * width + x] = u32::from(quantize_color(color, depth));
+                }
+            }
+        }
+        Dither::FloydSteinberg => {
+            let mut errors = vec![[0.0f32; 3]; width * height];
+            for y in 0..height {
+                for x in 0..width {
+                    let i = y * width + x;
+                    let color = Color::from(pixels[i]);
+                    let wanted = [
+                        f32::from(color.r) + errors[i][0],
+                        f32::from(color.g) + errors[i][1],
+                        f32::from(color.b) + errors[i][2],
+                    ];
+                    let quantized = quantize_color(
+                        Color {
+                            r: wanted[0].clamp(0.0, 255.0) as u8,
+                            g: wanted[1].clamp(0.0, 255.0) as u8,
+                            b: wanted[2].clamp(0.0, 255.0) as u8,
+                        },
+                        depth,
+                    );
+                    pixels[i] = u32::from(quantized);
+
+                    let error = [
+                        wanted[0] - f32::from(quantized.r),
+                        wanted[1] - f32::from(quantized.g),
+                        wanted[2] - f32::from(quantized.b),
+                    ];
+                    let mut spread_error = |x: usize, y: usize, weight: f32| {
+                        if x < width && y < height {
+                            for channel in 0..3 {
+                                errors[y * width + x][channel] += error[channel] * weight;
+                            }
+                        }
+                    };
+                    spread_error(x + 1, y, 7.0 / 16.0);
+                    if x > 0 {
+                        spread_error(x - 1, y + 1, 3.0 / 16.0);
+                    }
+                    spread_error(x, y + 1, 5.0 / 16.0);
+                    spread_error(x + 1, y + 1, 1.0 / 16.0);
+                }
+            }
+        }
+    }
+}
+
+/// Writes the SGR sequence that selects `color` as the foreground, or the background when `background` is set.
+pub fn write_sgr<W: Write>(
+    out: &mut W,
+    color: Color,
+    depth: ColorDepth,
+    background: bool,
+) -> std::io::Result<()> {
+    if depth == ColorDepth::Monochrome {
+        return Ok(());
+    }
+    write!(out, "\x1b[")?;
+    write_sgr_params(out, Some(color), depth, background)?;
+    write!(out, "m")
+}
+
+/// Writes only the SGR parameters (without `ESC [` and `m`) that select `color`,
+/// or the terminal's default color when it is `None`. Writes nothing for `ColorDepth::Monochrome`.
+pub fn write_sgr_params<W: Write>(
+    out: &mut W,
+    color: Option<Color>,
+    depth: ColorDepth,
+    background: bool,
+) -> std::io::Result<()> {
+    let color = match color {
+        Some(color) => color,
+        None => return write!(out, "{}", if background { 49 } else { 39 }),
+    };
+
+    match depth {
+        ColorDepth::Monochrome => Ok(()),
+        ColorDepth::TrueColor => {
+            let kind = if background { 48 } else { 38 };
+            write!(out, "{};2;{};{};{}", kind, color.r, color.g, color.b)
+        }
+        ColorDepth::Ansi256 => {
+            let kind = if background { 48 } else { 38 };
+            write!(out, "{};5;{}", kind, quantize(color, depth))
+        }
+        ColorDepth::Ansi16 => {
+            let index = quantize(color, depth);
+            let base = match (background, index < 8) {
+                (false, true) => 30,
+                (false, false) => 90 - 8,
+                (true, true) => 40,
+                (true, false) => 100 - 8,
+            };
+            write!(out, "{}", base + index)
+        }
+    }
+}
+
+fn nearest_cube_level(value: u8) -> u8 {
+    match value {
+        0..=47 => 0,
+        48..=114 => 1,
+        _ => (value - 35) / 40,
+    }
+}
+
+fn nearest(color: Color, indices: std::ops::RangeInclusive<u8>) -> u8 {
+    indices
+        .min_by_key(|index| distance(color, palette_color(*index)))
+        .unwrap()
+}
+
+/// How different two colors look, as the "redmean" weighted RGB distance, from 0.0 up to about 765.0
+pub fn perceptual_distance(a: Color, b: Color) -> f32 {
+    let mean_r = (f32::from(a.r) + f32::from(b.r)) / 2.0;
+    let dr = f32::from(a.r) - f32::from(b.r);
+    let dg = f32::from(a.g) - f32::from(b.g);
+    let db = f32::from(a.b) - f32::from(b.b);
+    ((2.0 + mean_r / 256.0) * dr * dr + 4.0 * dg * dg + (2.0 + (255.0 - mean_r) / 256.0) * db * db).sqrt()
+}
+
+fn distance(a: Color, b: Color) -> u32 {
+    let dr = i32::from(a.r) - i32::from(b.r);
+    let dg = i32::from(a.g) - i32::from(b.g);
+    let db = i32::from(a.b) - i32::from(b.b);
+    (dr * dr + dg * dg + db * db) as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rgb(r: u8, g: u8, b: u8) -> Color {
+        Color { r, g, b }
+    }
+
+    #[test]
+    fn palette_colors_quantize_to_themselves() {
+        for index in 16..=255u8 {
+            let color = palette_color(index);
+            assert_eq!(quantize(color, ColorDepth::Ansi256), index);
+        }
+        for index in 0..=15u8 {
+            let color = palette_color(index);
+            assert_eq!(quantize(color, ColorDepth::Ansi16), index);
+        }
+    }
+
+    #[test]
+    fn cube_levels_round_to_nearest() {
+        for value in 0..=255u8 {
+            let level = CUBE_LEVELS[nearest_cube_level(value) as usize];
+            let best = CUBE_LEVELS
+                .iter()
+                .map(|level| (i32::from(*level) - i32::from(value)).abs())
+                .min()
+                .unwrap();
+            assert_eq!((i32::from(level) - i32::from(value)).abs(), best, "value {}", value);
+        }
+    }
+
+    #[test]
+    fn greys_use_the_greyscale_ramp() {
+        assert_eq!(quantize(rgb(128, 128, 128), ColorDepth::Ansi256), 244);
+        assert_eq!(quantize(rgb(0, 0, 0), ColorDepth::Ansi256), 16);
+        assert_eq!(quantize(rgb(255, 255, 255), ColorDepth::Ansi256), 231);
+    }
+
+    #[test]
+    fn base_colors_pick_nearest() {
+        assert_eq!(quantize(rgb(250, 10, 10), ColorDepth::Ansi16), 9);
+        assert_eq!(quantize(rgb(190, 0, 0), ColorDepth::Ansi16), 1);
+        assert_eq!(quantize(rgb(20, 20, 20), ColorDepth::Ansi16), 0);
+    }
+
+    #[test]
+    fn sgr_sequences_match_depth() {
+        let mut out = Vec::new();
+        write_sgr(&mut out, rgb(1, 2, 3), ColorDepth::TrueColor, false).unwrap();
+        write_sgr(&mut out, rgb(255, 0, 0), ColorDepth::Ansi256, true).unwrap();
+        write_sgr(&mut out, rgb(255, 0, 0), ColorDepth::Ansi16, false).unwrap();
+        write_sgr(&mut out, rgb(205, 0, 0), ColorDepth::Ansi16, true).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "\x1b[38;2;1;2;3m\x1b[48;5;196m\x1b[91m\x1b[41m"
+        );
+    }
+
+    #[test]
+    fn dithering_produces_palette_colors() {
+        for dither in [Dither::None, Dither::Ordered, Dither::FloydSteinberg] {
+            let mut pixels: Vec<u32> = (0..64u32).map(|i| i * 0x030507).collect();
+            dither_buffer(&mut pixels, 8, 8, ColorDepth::Ansi16, dither);
+            for pixel in pixels {
+                let color = Color::from(pixel);
+                assert_eq!(u32::from(palette_color(quantize(color, ColorDepth::Ansi16))), pixel);
+            }
+        }
+    }
+
+    #[test]
+    fn floyd_steinberg_preserves_average() {
+        let grey = u32::from(rgb(64, 64, 64));
+        let mut pixels = vec![grey; 16 * 16];
+        dither_buffer(&mut pixels, 16, 16, ColorDepth::Ansi16, Dither::FloydSteinberg);
+
+        let average = pixels.iter().map(|pixel| pixel & 0xff).sum::<u32>() / pixels.len() as u32;
+        assert!((56..=72).contains(&average), "average {}", average);
+        assert!(pixels.iter().any(|pixel| *pixel != pixels[0]));
+    }
+
+    #[test]
+    fn truecolor_is_untouched() {
+        let mut pixels = vec![0x123456; 4];
+        dither_buffer(&mut pixels, 2, 2, ColorDepth::TrueColor, Dither::FloydSteinberg);
+        assert_eq!(pixels, vec![0x123456; 4]);
+    }
+}
@@ -0,0 +1,97 @@
+use serde_json::{json, Value};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+/// Writes terminal output to an asciicast v2 file, asciinema's JSON-lines format:
+/// a header with the terminal size followed by one `[time, "o", data]` event per write.
+pub struct AsciicastRecorder<W: Write = BufWriter<File>> {
+    out: W,
+    start: Instant,
+}
+
+impl AsciicastRecorder {
+    /// Creates the file at `path` and writes the header for a terminal of `columns` x `rows`
+    pub fn create<P: AsRef<Path>>(path: P, columns: usize, rows: usize) -> std::io::Result<AsciicastRecorder> {
+        AsciicastRecorder::with_writer(BufWriter::new(File::create(path)?), columns, rows)
+    }
+}
+
+impl<W: Write> AsciicastRecorder<W> {
+    /// Writes the header for a terminal of `columns` x `rows` to `out`
+    pub fn with_writer(mut out: W, columns: usize, rows: usize) -> std::io::Result<AsciicastRecorder<W>> {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |duration| duration.as_secs());
+        let mut header = json!({
+            "version": 2,
+            "width": columns,
+            "height": rows,
+            "timestamp": timestamp,
+        });
+        if let Ok(term) = std::env::var("TERM") {
+            header["env"] = json!({ "TERM": term });
+        }
+        writeln!(out, "{}", header)?;
+
+        Ok(AsciicastRecorder {
+            out,
+            start: Instant::now(),
+        })
+    }
+
+    /// Records `data` as output written to the terminal now
+    pub fn write_output(&mut self, data: &[u8]) -> std::io::Result<()> {
+        if data.is_empty() {
+            return Ok(());
+        }
+        self.write_event("o", String::from_utf8_lossy(data).into_owned())
+    }
+
+    /// Records the terminal being resized to `columns` x `rows`
+    pub fn write_resize(&mut self, columns: usize, rows: usize) -> std::io::Result<()> {
+        self.write_event("r", format!("{}x{}", columns, rows))
+    }
+
+    /// Writes the events recorded so far through to the file, so a crash does not lose them
+    pub fn flush(&mut self) -> std::io::Result<()> {
+        self.out.flush()
+    }
+
+    /// Flushes the recording and returns the sink
+    pub fn finish(mut self) -> std::io::Result<W> {
+        self.out.flush()?;
+        Ok(self.out)
+    }
+
+    fn write_event(&mut self, code: &str, data: String) -> std::io::Result<()> {
+        let time = (self.start.elapsed().as_secs_f64() * 1_000_000.0).round() / 1_000_000.0;
+        let event: Value = json!([time, code, data]);
+        writeln!(self.out, "{}", event)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn header_and_events_are_json_lines() {
+        let mut recorder = AsciicastRecorder::with_writer(Vec::new(), 80, 24).unwrap();
+        recorder.write_output(b"\x1b[1;1Hx").unwrap();
+        recorder.write_output(b"").unwrap();
+        recorder.write_resize(100, 30).unwrap();
+        let out = String::from_utf8(recorder.finish().unwrap()).unwrap();
+
+        let lines: Vec<Value> = out.lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0]["version"], 2);
+        assert_eq!(lines[0]["width"], 80);
+        assert_eq!(lines[0]["height"], 24);
+        assert_eq!(lines[1][1], "o");
+        assert_eq!(lines[1][2], "\x1b[1;1Hx");
+        assert_eq!(lines[2][1], "r");
+        assert_eq!(lines[2][2], "100x30");
+    }
+}
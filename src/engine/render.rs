@@ -0,0 +1,623 @@
+use super::ascii_render::{Color, TerminalFrameBuffer};
+use super::camera::Camera;
+use super::core::Game;
+use super::edges;
+use super::error::EngineError;
+use super::matrices;
+use super::object::TextureFilter;
+use glium::backend::Facade;
+use glium::texture::pixel_buffer::PixelBuffer;
+use glium::Surface;
+use rayon::prelude::*;
+use std::io::Write;
+use std::ops::Range;
+use std::path::PathBuf;
+
+/// RGBA image produced by a renderer, bottom row first like OpenGL textures.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Readback {
+    pub width: u32,
+    pub height: u32,
+    pub data: Vec<u8>,
+}
+
+/// Everything a renderer produced for one frame.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RenderOutput {
+    pub color: Readback,
+    /// Normals and depth encoded like `edges::EDGE_FRAGMENT_SHADER`, when they were asked for
+    pub edges: Option<Readback>,
+}
+
+/// Shader files of the scene and UI passes, relative to the assets folder
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ShaderPaths {
+    pub vertex: String,
+    pub fragment: String,
+    pub ui_vertex: String,
+    pub ui_fragment: String,
+}
+
+impl Default for ShaderPaths {
+    fn default() -> Self {
+        ShaderPaths {
+            vertex: "/shaders/vertex_shader.glsl".to_string(),
+            fragment: "/shaders/fragment_shader.glsl".to_string(),
+            ui_vertex: "/shaders/ui_vertex.glsl".to_string(),
+            ui_fragment: "/shaders/ui_fragment.glsl".to_string(),
+        }
+    }
+}
+
+/// How the scene and UI are drawn.
+/// `SoftwareRenderer` only uses the clear color and the light, it always draws like the default shaders and parameters.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RenderSettings {
+    /// Color of the sky, everywhere the scene has no objects
+    pub clear_color: Color,
+    /// Direction the light comes from, passed to the fragment shader as `u_light`
+    pub light: [f32; 3],
+    pub shaders: ShaderPaths,
+    /// Depth test of the scene pass, the UI pass has none
+    pub depth_test: glium::draw_parameters::DepthTest,
+    pub blend: glium::Blend,
+    pub ui_blend: glium::Blend,
+    /// Culling of the scene and UI passes
+    pub backface_culling: glium::draw_parameters::BackfaceCullingMode,
+}
+
+impl Default for RenderSettings {
+    fn default() -> Self {
+        RenderSettings {
+            clear_color: Color { r: 105, g: 109, b: 219 },
+            light: [1.4, 0.4, -0.7f32],
+            shaders: ShaderPaths::default(),
+            depth_test: glium::draw_parameters::DepthTest::IfLess,
+            blend: glium::Blend::alpha_blending(),
+            ui_blend: glium::Blend::alpha_blending(),
+            backface_culling: glium::draw_parameters::BackfaceCullingMode::CullClockwise,
+        }
+    }
+}
+
+/// Draws the scene and UI of a `Game`.
+pub trait Renderer {
+    /// Renders the current scene and UI of `game` as seen from `camera` at `width` x `height` pixels.
+    /// With `edges` set the normals and depth for `edges::detect_edges` are rendered as well.
+    fn render(&mut self, game: &Game, camera: &Camera, width: u32, height: u32, edges: bool) -> RenderOutput;
+
+    /// With `latency` set `render` may return the previous frame while the current one is still being read back.
+    /// Renderers that always finish the frame ignore it.
+    fn set_readback_latency(&mut self, _latency: bool) {}
+}
+
+/// Renders `game` as seen from `camera` with `renderer` into the back buffer of `terminal_fb`, using its output settings.
+/// Renders `OutputSettings::supersampling` pixels per framebuffer pixel and averages them. Returns the rendered image.
+pub fn render_to_framebuffer<W: Write>(
+    renderer: &mut dyn Renderer,
+    game: &Game,
+    camera: &Camera,
+    terminal_fb: &mut TerminalFrameBuffer<W>,
+) -> Readback {
+    let (pixel_width, pixel_height) = terminal_fb.size();
+    let (samples_x, samples_y) = terminal_fb.settings().supersampling;
+    let render_res = ((pixel_width * samples_x.max(1)) as u32, (pixel_height * samples_y.max(1)) as u32);
+    let edge_settings = terminal_fb.settings().edges;
+
+    renderer.set_readback_latency(terminal_fb.settings().readback_latency);
+    let output = renderer.render(game, camera, render_res.0, render_res.1, edge_settings.is_some());
+    let pixels = output.color;
+
+    terminal_fb.clear();
+    for (i, color) in average_blocks(&pixels, pixel_width, pixel_height).into_iter().enumerate() {
+        terminal_fb.set_pixel(i % pixel_width, i / pixel_width, color);
+    }
+
+    if let (Some(edge_settings), Some(edge_pixels)) = (&edge_settings, &output.edges) {
+        // normals and depth are not averaged, the sample in the middle of every block stands for it
+        let mut edge_samples = vec![[0u8; 4]; pixel_width * pixel_height];
+        for_each_block(edge_pixels, pixel_width, pixel_height, |x, y, rows, columns| {
+            let i = ((rows.start + rows.len() / 2) * edge_pixels.width as usize + columns.start + columns.len() / 2) * 4;
+            edge_samples[y * pixel_width + x].copy_from_slice(&edge_pixels.data[i..i + 4]);
+        });
+
+        let detected = edges::detect_edges(&edge_samples, pixel_width, pixel_height, edge_settings);
+        for (i, edge) in detected.into_iter().enumerate() {
+            terminal_fb.set_edge(i % pixel_width, i / pixel_width, edge);
+        }
+    }
+
+    pixels
+}
+
+/// Averages the pixels of every block of `image` as split by `for_each_block`, top row first.
+/// Rows are averaged in parallel, blocks not covering any pixel stay black.
+fn average_blocks(image: &Readback, width: usize, height: usize) -> Vec<Color> {
+    let mut averaged = vec![Color { r: 0, g: 0, b: 0 }; width * height];
+    let (image_width, image_height) = (image.width as usize, image.height as usize);
+    if width == 0 || image_width == 0 || image_height == 0 {
+        return averaged;
+    }
+
+    averaged.par_chunks_mut(width).enumerate().for_each(|(y, row)| {
+        // the image is stored bottom row first
+        let rows = block_span(height - 1 - y, height, image_height);
+        for (x, color) in row.iter_mut().enumerate() {
+            let columns = block_span(x, width, image_width);
+            let mut sum = [0u32; 3];
+            for image_row in rows.clone() {
+                for column in columns.clone() {
+                    let i = (image_row * image_width + column) * 4;
+                    for (channel, value) in sum.iter_mut().enumerate() {
+                        *value += u32::from(image.data[i + channel]);
+                    }
+                }
+            }
+            let count = (rows.len() * columns.len()) as u32;
+            let [r, g, b] = sum.map(|value| ((value + count / 2) / count) as u8);
+            *color = Color { r, g, b };
+        }
+    });
+    averaged
+}
+
+/// Splits `image` into `width` x `height` blocks of whole pixels and calls `block` with the position of every block,
+/// top row first, and the rows and columns of `image` it covers. Every block covers at least one pixel.
+fn for_each_block<F>(image: &Readback, width: usize, height: usize, mut block: F)
+where
+    F: FnMut(usize, usize, Range<usize>, Range<usize>),
+{
+    let (image_width, image_height) = (image.width as usize, image.height as usize);
+    if image_width == 0 || image_height == 0 {
+        return;
+    }
+
+    for y in 0..height {
+        // the image is stored bottom row first
+        let rows = block_span(height - 1 - y, height, image_height);
+        for x in 0..width {
+            block(x, y, rows.clone(), block_span(x, width, image_width));
+        }
+    }
+}
+
+/// Pixels of an image `image_size` pixels long covered by block `i` of `size` blocks, at least one
+fn block_span(i: usize, size: usize, image_size: usize) -> Range<usize> {
+    let start = (i * image_size / size).min(image_size - 1);
+    start..((i + 1) * image_size / size).clamp(start + 1, image_size)
+}
+
+/// Pixel buffer holding an RGBA8 target after it was read back
+type ReadbackBuffer = PixelBuffer<(u8, u8, u8, u8)>;
+
+/// Offscreen targets the scene is drawn into, kept between frames of the same size
+struct RenderTargets {
+    size: (u32, u32),
+    color: glium::texture::Texture2d,
+    depth: glium::framebuffer::DepthRenderBuffer,
+    /// Normals and depth of the scene for the edge pass, glium can not read the depth buffer back directly
+    edges: Option<glium::texture::Texture2d>,
+    /// Two sets of pixel buffers the targets are read back into, so one can be mapped while the other is written
+    color_readbacks: [ReadbackBuffer; 2],
+    edge_readbacks: Option<[ReadbackBuffer; 2]>,
+    /// The set the next frame is read back into
+    next_readback: usize,
+    /// Set and edge pass of the frame that was read back but not returned yet
+    pending_readback: Option<(usize, bool)>,
+}
+
+impl RenderTargets {
+    fn new(facade: &impl Facade, size: (u32, u32)) -> RenderTargets {
+        let color = glium::texture::Texture2d::empty_with_format(
+            facade,
+            glium::texture::UncompressedFloatFormat::U8U8U8U8,
+            glium::texture::MipmapsOption::NoMipmap,
+            size.0,
+            size.1,
+        )
+        .unwrap();
+
+        // Create a depth buffer for off-screen rendering
+        let depth = glium::framebuffer::DepthRenderBuffer::new(
+            facade,
+            glium::texture::DepthFormat::F32,
+            size.0,
+            size.1,
+        )
+        .unwrap();
+
+        RenderTargets {
+            size,
+            color,
+            depth,
+            edges: None,
+            color_readbacks: [RenderTargets::pixel_buffer(facade, size), RenderTargets::pixel_buffer(facade, size)],
+            edge_readbacks: None,
+            next_readback: 0,
+            pending_readback: None,
+        }
+    }
+
+    fn pixel_buffer(facade: &impl Facade, size: (u32, u32)) -> ReadbackBuffer {
+        PixelBuffer::new_empty(facade, size.0 as usize * size.1 as usize)
+    }
+
+    /// Adds the target of the edge pass, if it does not exist yet
+    fn add_edges(&mut self, facade: &impl Facade) {
+        if self.edges.is_some() {
+            return;
+        }
+        self.edges = Some(
+            glium::texture::Texture2d::empty_with_format(
+                facade,
+                glium::texture::UncompressedFloatFormat::U8U8U8U8,
+                glium::texture::MipmapsOption::NoMipmap,
+                self.size.0,
+                self.size.1,
+            )
+            .unwrap(),
+        );
+        self.edge_readbacks = Some([
+            RenderTargets::pixel_buffer(facade, self.size),
+            RenderTargets::pixel_buffer(facade, self.size),
+        ]);
+    }
+
+    /// Starts copying the drawn frame into the next set of pixel buffers without waiting for the GPU.
+    /// Returns the set it is copied into.
+    fn start_readback(&mut self, edges: bool) -> usize {
+        let set = self.next_readback;
+        self.next_readback = 1 - set;
+
+        let copy = |texture: &glium::texture::Texture2d, buffer: &ReadbackBuffer| {
+            let rect = glium::Rect {
+                left: 0,
+                bottom: 0,
+                width: texture.width(),
+                height: texture.height(),
+            };
+            texture
+                .main_level()
+                .first_layer()
+                .into_image(None)
+                .unwrap()
+                .raw_read_to_pixel_buffer(&rect, buffer);
+        };
+        copy(&self.color, &self.color_readbacks[set]);
+        if edges {
+            if let (Some(texture), Some(buffers)) = (&self.edges, &self.edge_readbacks) {
+                copy(texture, &buffers[set]);
+            }
+        }
+        set
+    }
+
+    /// Maps a set of pixel buffers, waiting for the copy into it to finish
+    fn finish_readback(&self, set: usize, edges: bool) -> RenderOutput {
+        let read = |buffer: &ReadbackBuffer| Readback {
+            width: self.size.0,
+            height: self.size.1,
+            data: buffer
+                .read()
+                .unwrap()
+                .into_iter()
+                .flat_map(|(r, g, b, a)| [r, g, b, a])
+                .collect(),
+        };
+
+        RenderOutput {
+            color: read(&self.color_readbacks[set]),
+            edges: self
+                .edge_readbacks
+                .as_ref()
+                .filter(|_| edges)
+                .map(|buffers| read(&buffers[set])),
+        }
+    }
+}
+
+/// Reads and builds the program made of the shaders at `{assets_path}{vertex}` and `{assets_path}{fragment}`
+fn load_program(facade: &impl Facade, assets_path: &str, vertex: &str, fragment: &str) -> Result<glium::Program, EngineError> {
+    let vertex_path = PathBuf::from(format!("{}{}", assets_path, vertex));
+    let fragment_path = PathBuf::from(format!("{}{}", assets_path, fragment));
+    let vertex_shader_src =
+        std::fs::read_to_string(&vertex_path).map_err(|source| EngineError::io(&vertex_path, source))?;
+    let fragment_shader_src =
+        std::fs::read_to_string(&fragment_path).map_err(|source| EngineError::io(&fragment_path, source))?;
+
+    build_program(facade, vertex_path, &vertex_shader_src, fragment_path, &fragment_shader_src)
+}
+
+/// Builds the program made of the shader sources `vertex` and `fragment`, errors name them `vertex_path` and `fragment_path`
+fn build_program(
+    facade: &impl Facade,
+    vertex_path: PathBuf,
+    vertex: &str,
+    fragment_path: PathBuf,
+    fragment: &str,
+) -> Result<glium::Program, EngineError> {
+    glium::Program::from_source(facade, vertex, fragment, None).map_err(|error| EngineError::Shader {
+        vertex: vertex_path,
+        fragment: fragment_path,
+        message: error.to_string(),
+    })
+}
+
+/// Renders with OpenGL on a glium facade, the window of `run_event_loop` as well as a headless context.
+/// Only objects uploaded to the same facade are drawn.
+pub struct GlRenderer<F: Facade> {
+    facade: F,
+    /// rebuilt when the render size changes
+    targets: Option<RenderTargets>,
+    readback_latency: bool,
+    program: glium::Program,
+    ui_program: glium::Program,
+    params: glium::DrawParameters<'static>,
+    ui_params: glium::DrawParameters<'static>,
+    edge_program: glium::Program,
+    edge_params: glium::DrawParameters<'static>,
+    clear_color: Color,
+    light: [f32; 3],
+}
+
+impl<F: Facade> GlRenderer<F> {
+    /// Loads the scene and UI shaders from `{assets_path}/shaders`
+    pub fn load(facade: F, assets_path: &str) -> Result<GlRenderer<F>, EngineError> {
+        GlRenderer::with_settings(facade, assets_path, &RenderSettings::default())
+    }
+
+    /// Loads the shaders of `settings` from `assets_path` and draws with its parameters
+    pub fn with_settings(facade: F, assets_path: &str, settings: &RenderSettings) -> Result<GlRenderer<F>, EngineError> {
+        let shaders = &settings.shaders;
+        let program = load_program(&facade, assets_path, &shaders.vertex, &shaders.fragment)?;
+        let ui_program = load_program(&facade, assets_path, &shaders.ui_vertex, &shaders.ui_fragment)?;
+
+        let params = glium::DrawParameters {
+            depth: glium::Depth {
+                test: settings.depth_test,
+                write: true,
+                ..Default::default()
+            },
+            blend: settings.blend,
+            backface_culling: settings.backface_culling,
+            ..Default::default()
+        };
+
+        let ui_params = glium::DrawParameters {
+            blend: settings.ui_blend,
+            backface_culling: settings.backface_culling,
+            ..Default::default()
+        };
+
+        let mut renderer = GlRenderer::new(facade, program, ui_program, params, ui_params)?;
+        renderer.clear_color = settings.clear_color;
+        renderer.light = settings.light;
+        Ok(renderer)
+    }
+
+    /// Creates a renderer from already built programs and draw parameters, only the program of the edge pass is built here
+    pub fn new(
+        facade: F,
+        program: glium::Program,
+        ui_program: glium::Program,
+        params: glium::DrawParameters<'static>,
+        ui_params: glium::DrawParameters<'static>,
+    ) -> Result<GlRenderer<F>, EngineError> {
+        let edge_program = build_program(
+            &facade,
+            PathBuf::from("edges::EDGE_VERTEX_SHADER"),
+            edges::EDGE_VERTEX_SHADER,
+            PathBuf::from("edges::EDGE_FRAGMENT_SHADER"),
+            edges::EDGE_FRAGMENT_SHADER,
+        )?;
+        let edge_params = glium::DrawParameters {
+            blend: glium::Blend::default(),
+            ..params.clone()
+        };
+
+        Ok(GlRenderer {
+            facade,
+            targets: None,
+            readback_latency: false,
+            program,
+            ui_program,
+            params,
+            ui_params,
+            edge_program,
+            edge_params,
+            clear_color: RenderSettings::default().clear_color,
+            light: RenderSettings::default().light,
+        })
+    }
+
+    /// The facade objects have to be uploaded to
+    pub fn get_facade(&self) -> &F {
+        &self.facade
+    }
+
+    /// Drops the offscreen targets and a pending readback, the next frame allocates them again
+    pub fn release_targets(&mut self) {
+        self.targets = None;
+    }
+}
+
+impl<F: Facade> Renderer for GlRenderer<F> {
+    fn set_readback_latency(&mut self, latency: bool) {
+        self.readback_latency = latency;
+    }
+
+    fn render(&mut self, game: &Game, camera: &Camera, width: u32, height: u32, edges: bool) -> RenderOutput {
+        let facade = &self.facade;
+        let render_res = (width, height);
+
+        let targets = match &mut self.targets {
+            Some(targets) if targets.size == render_res => targets,
+            targets => targets.insert(RenderTargets::new(facade, render_res)),
+        };
+        if edges {
+            targets.add_edges(facade);
+        }
+        let texture = &targets.color;
+        let depthbuffer = &targets.depth;
+        let edge_texture = if edges { targets.edges.as_ref() } else { None };
+
+        // Create a framebuffer for off-screen rendering
+        let mut framebuffer: glium::framebuffer::SimpleFrameBuffer =
+            glium::framebuffer::SimpleFrameBuffer::with_depth_buffer(
+                facade,
+                texture,
+                depthbuffer,
+            )
+            .unwrap();
+
+        framebuffer
+            .clear_color_and_depth(
+                (
+                    f32::from(self.clear_color.r) / 255.0,
+                    f32::from(self.clear_color.g) / 255.0,
+                    f32::from(self.clear_color.b) / 255.0,
+                    1.0,
+                ),
+                1.0,
+            );
+
+        for (object, gpu) in game.get_scene().objects.iter().filter_map(|object| Some((object, object.gpu.as_ref()?))) {
+            let uniforms = uniform! {
+                model: object.model,
+                view: camera.view_matrix(),
+                perspective: camera.perspective_matrix(),
+                u_light: self.light,
+                tex: &gpu.texture,
+            };
+
+            framebuffer
+                .draw(&gpu.vb, &gpu.ib, &self.program, &uniforms, &self.params)
+                .unwrap();
+        }
+
+        //--------------------------------- Edges ---------------------------------
+
+        if let Some(edge_texture) = edge_texture {
+            // The scene shaders are assets that only write colors, so normals are not in any attachment yet and the
+            // scene is drawn a second time for them. This pass runs only while edges are on and does its own depth
+            // test, the depth buffer is cleared as the pass writes view depth next to the normals anyway.
+            let mut edge_framebuffer = glium::framebuffer::SimpleFrameBuffer::with_depth_buffer(
+                facade,
+                edge_texture,
+                depthbuffer,
+            )
+            .unwrap();
+            edge_framebuffer.clear_color_and_depth(edges::EDGE_CLEAR_COLOR, 1.0);
+
+            for (object, gpu) in game.get_scene().objects.iter().filter_map(|object| Some((object, object.gpu.as_ref()?))) {
+                let uniforms = uniform! {
+                    model: object.model,
+                    view: camera.view_matrix(),
+                    perspective: camera.perspective_matrix(),
+                    znear: matrices::ZNEAR,
+                    zfar: matrices::ZFAR,
+                };
+
+                edge_framebuffer
+                    .draw(&gpu.vb, &gpu.ib, &self.edge_program, &uniforms, &self.edge_params)
+                    .unwrap();
+            }
+        }
+
+        //--------------------------------- UI ---------------------------------
+
+        for (ui_elem, gpu) in game.get_ui_elems().elems.iter().filter_map(|elem| Some((elem, elem.gpu.as_ref()?))) {
+            let behavior = match ui_elem.texture_filter {
+                TextureFilter::Nearest => glium::uniforms::SamplerBehavior {
+                    minify_filter: glium::uniforms::MinifySamplerFilter::Nearest,
+                    magnify_filter: glium::uniforms::MagnifySamplerFilter::Nearest,
+                    ..Default::default()
+                },
+                TextureFilter::Linear => glium::uniforms::SamplerBehavior {
+                    minify_filter: glium::uniforms::MinifySamplerFilter::Linear,
+                    magnify_filter: glium::uniforms::MagnifySamplerFilter::Linear,
+                    ..Default::default()
+                },
+            };
+
+            let uniforms = uniform! {
+                tex: glium::uniforms::Sampler(&gpu.texture, behavior),
+            };
+
+            framebuffer
+                .draw(&gpu.vb, &gpu.ib, &self.ui_program, &uniforms, &self.ui_params)
+                .unwrap();
+        }
+
+        //get pixels from display, the copy into the pixel buffers runs asynchronously
+        let set = targets.start_readback(edges);
+        if !self.readback_latency {
+            targets.pending_readback = None;
+            return targets.finish_readback(set, edges);
+        }
+
+        // show the previous frame, its copy had the whole frame to finish
+        match targets.pending_readback.replace((set, edges)) {
+            Some((previous, previous_edges)) if previous_edges || !edges => targets.finish_readback(previous, edges),
+            _ => targets.finish_readback(set, edges),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::ascii_render::{CellMode, OutputSettings};
+    use crate::engine::scene::Scene;
+
+    /// Returns a fixed image, whatever it is asked to render
+    struct Fixed(Readback);
+
+    impl Renderer for Fixed {
+        fn render(&mut self, _game: &Game, _camera: &Camera, _width: u32, _height: u32, _edges: bool) -> RenderOutput {
+            RenderOutput {
+                color: self.0.clone(),
+                edges: None,
+            }
+        }
+    }
+
+    #[test]
+    fn blocks_are_averaged_top_row_first() {
+        // 4x2 pixels, bottom row first: a black and white checkerboard on the left, red on the right, blue on top
+        let black = [0, 0, 0, 255];
+        let white = [255, 255, 255, 255];
+        let red = [255, 0, 0, 255];
+        let blue = [0, 0, 255, 255];
+        let rows = [[black, white, red, red], [white, black, blue, blue]];
+        let mut renderer = Fixed(Readback {
+            width: 4,
+            height: 2,
+            data: rows.iter().flatten().flatten().copied().collect(),
+        });
+
+        let camera = Camera::new([0.0; 3], [0.0; 3], 0.05, 0.05, (2, 1));
+        let mut game = Game::new(camera, String::new());
+        game.add_scene(Scene::new());
+        let settings = OutputSettings {
+            cell_mode: CellMode::HalfBlock,
+            supersampling: (2, 1),
+            ..Default::default()
+        };
+        let (width, height) = CellMode::default().pixel_size(2, 1);
+        let mut terminal_fb = TerminalFrameBuffer::with_writer(width, height, Color { r: 0, g: 0, b: 0 }, std::io::sink());
+        terminal_fb.set_settings(settings);
+        assert_eq!(terminal_fb.size(), (2, 2));
+
+        render_to_framebuffer(&mut renderer, &game, &game.camera, &mut terminal_fb);
+        terminal_fb.draw_frame().unwrap();
+        let cells = terminal_fb.front_cells();
+
+        let grey = Color { r: 128, g: 128, b: 128 };
+        assert_eq!(cells[0].background, Some(grey));
+        assert_eq!(
+            (cells[1].foreground, cells[1].background),
+            (Some(Color { r: 0, g: 0, b: 255 }), Some(Color { r: 255, g: 0, b: 0 }))
+        );
+    }
+}
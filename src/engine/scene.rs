@@ -1,6 +1,8 @@
 
-use super::object::Object;
-use serde_json::{Result, Value};
+use super::error::EngineError;
+use super::object::{Object, Upload};
+use serde_json::Value;
+use std::path::Path;
 
 pub struct Scene {
     pub objects: Vec<Object>,
@@ -13,9 +15,12 @@ impl Scene {
         }
     }
 
-    pub fn load_from_json(filepath: &str, assets_path: &str, display: &glium::Display) -> Result<Scene> {
-        let json = std::fs::read_to_string((assets_path.to_owned()+filepath).as_str()).unwrap();
-        let v: Value = serde_json::from_str(json.as_str())?;
+    /// Loads the scene exported to `{assets_path}{filepath}` together with its models and textures
+    pub fn load_from_json(filepath: &str, assets_path: &str, display: &(impl Upload + ?Sized)) -> Result<Scene, EngineError> {
+        let scene_path = assets_path.to_owned() + filepath;
+        let path = Path::new(&scene_path);
+        let json = std::fs::read_to_string(path).map_err(|source| EngineError::io(path, source))?;
+        let v: Value = serde_json::from_str(json.as_str()).map_err(|error| EngineError::scene(path, error))?;
         let mut scene = Scene::new();
 
         /*
@@ -77,69 +82,70 @@ impl Scene {
         
          */
 
-        for object in v.as_array().unwrap().iter() {
-            let object = object.as_object().unwrap();
-
-            let object_name = object.keys().next().unwrap();
-
-            let object = object.get(object_name).unwrap();
-
-            let model_path = object.get("model_path").unwrap().as_str().unwrap();
-
-            let texture_path = object.get("texture_path").unwrap().as_str();
-
-            let model_matrix = object.get("model_matrix").unwrap();
-
-            let position = model_matrix.get("position").unwrap().as_array().unwrap();
-
-            let rotation = model_matrix.get("rotation").unwrap().as_array().unwrap();
+        let objects = v
+            .as_array()
+            .ok_or_else(|| EngineError::scene(path, "expected an array of objects"))?;
+
+        for object in objects.iter() {
+            let (object_name, object) = object
+                .as_object()
+                .and_then(|object| object.iter().next())
+                .ok_or_else(|| EngineError::scene(path, "expected objects with their name as the only key"))?;
+            let invalid = |field: &str| EngineError::scene(path, format!("{}: missing or invalid {}", object_name, field));
+
+            let model_path = object
+                .get("model_path")
+                .and_then(Value::as_str)
+                .ok_or_else(|| invalid("model_path"))?;
+
+            let texture_path = match object.get("texture_path") {
+                Some(Value::Null) => None,
+                Some(Value::String(texture_path)) => Some(assets_path.to_owned() + texture_path),
+                _ => return Err(invalid("texture_path")),
+            };
 
-            let scale = model_matrix.get("scale").unwrap().as_array().unwrap();
+            let model_matrix = object.get("model_matrix").ok_or_else(|| invalid("model_matrix"))?;
+            let vector = |field: &str| -> Result<[f32; 3], EngineError> {
+                let values = model_matrix
+                    .get(field)
+                    .and_then(Value::as_array)
+                    .filter(|values| values.len() == 3)
+                    .ok_or_else(|| invalid(&format!("model_matrix.{}", field)))?;
+                let mut vector = [0.0; 3];
+                for (component, value) in vector.iter_mut().zip(values) {
+                    *component = value.as_f64().ok_or_else(|| invalid(&format!("model_matrix.{}", field)))? as f32;
+                }
+                Ok(vector)
+            };
+            let position = vector("position")?;
+            let rotation = vector("rotation")?;
+            let scale = vector("scale")?;
 
-            let tags = object.get("tags").unwrap().as_array().unwrap();
+            let tags = object
+                .get("tags")
+                .and_then(Value::as_array)
+                .ok_or_else(|| invalid("tags"))?;
 
             let mut tags_vec: Vec<String> = Vec::new();
 
             for tag in tags.iter() {
-                tags_vec.push(tag.as_str().unwrap().to_string());
+                tags_vec.push(tag.as_str().ok_or_else(|| invalid("tags"))?.to_string());
             }
 
-            let texture_path = match texture_path {
-                Some(texture_path) => {
-
-                    Some(assets_path.to_owned()+texture_path)
-                },
-                None => None,
-            };
-
-            let texture_path: Option<&str> = texture_path.as_ref().map(|s| &s[..]);
-
             // println!("Rotation: {:?}", rotation);
 
             scene.add_object(Object::new(
                 (assets_path.to_owned()+model_path).as_str(),
-                texture_path,
+                texture_path.as_deref(),
                 super::object::TextureFilter::Linear,
                 super::matrices::model_matrix(
-                    &[
-                        -position[0].as_f64().unwrap() as f32,
-                        position[2].as_f64().unwrap() as f32,
-                        -position[1].as_f64().unwrap() as f32,
-                    ],
-                    &[
-                        -rotation[0].as_f64().unwrap() as f32,
-                        rotation[2].as_f64().unwrap() as f32,
-                        -rotation[1].as_f64().unwrap() as f32,
-                    ],
-                    &[
-                        scale[0].as_f64().unwrap() as f32,
-                        scale[1].as_f64().unwrap() as f32,
-                        scale[2].as_f64().unwrap() as f32,
-                    ],
+                    &[-position[0], position[2], -position[1]],
+                    &[-rotation[0], rotation[2], -rotation[1]],
+                    &scale,
                 ),
                 display,
                 tags_vec,
-            ));
+            )?);
 
         }
 
@@ -190,3 +196,44 @@ impl Default for Scene {
         Self::new()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::object::CpuOnly;
+
+    /// Writes `json` to a scene file in a fresh assets folder and loads it
+    fn load(name: &str, json: &str) -> (String, Result<Scene, EngineError>) {
+        let assets_path = std::env::temp_dir().join(format!("ascii_scene_{}_{}", name, std::process::id()));
+        std::fs::create_dir_all(&assets_path).unwrap();
+        std::fs::write(assets_path.join("scene.json"), json).unwrap();
+
+        let assets_path = assets_path.to_str().unwrap().to_string();
+        let result = Scene::load_from_json("/scene.json", &assets_path, &CpuOnly);
+        std::fs::remove_dir_all(&assets_path).unwrap();
+        (assets_path, result)
+    }
+
+    #[test]
+    fn missing_fields_name_the_object_and_file() {
+        let (assets_path, result) = load("schema", r#"[{"Cube": {"model_path": "/models/Cube.obj", "tags": []}}]"#);
+        let error = result.err().unwrap();
+        assert!(matches!(error, EngineError::Scene { .. }));
+        assert_eq!(error.path(), Some(Path::new(&format!("{}/scene.json", assets_path))));
+        assert!(error.to_string().contains("Cube: missing or invalid texture_path"));
+    }
+
+    #[test]
+    fn missing_models_report_their_path() {
+        let json = r#"[{"Cube": {
+            "model_path": "/models/Typo.obj",
+            "texture_path": null,
+            "model_matrix": {"position": [0, 0, 0], "rotation": [0, 0, 0], "scale": [1, 1, 1]},
+            "tags": ["cube"]
+        }}]"#;
+        let (assets_path, result) = load("model", json);
+        let error = result.err().unwrap();
+        assert!(matches!(error, EngineError::Io { .. }));
+        assert!(error.to_string().contains(&format!("{}/models/Typo.obj", assets_path)));
+    }
+}
@@ -0,0 +1,115 @@
+#[cfg(unix)]
+use super::terminal;
+#[cfg(unix)]
+use std::sync::atomic::{AtomicBool, AtomicI32, Ordering};
+
+/// Last signal received and not taken yet, 0 when there is none
+#[cfg(unix)]
+static RECEIVED: AtomicI32 = AtomicI32::new(0);
+
+/// A signal was caught since `install_handlers`, the next one kills the process
+#[cfg(unix)]
+static CAUGHT: AtomicBool = AtomicBool::new(false);
+
+#[cfg(unix)]
+extern "C" fn on_signal(signal: libc::c_int) {
+    if CAUGHT.swap(true, Ordering::SeqCst) {
+        // the game did not stop, leave the alternate screen before dying the way the signal kills by default
+        terminal::leave_session();
+        unsafe {
+            libc::signal(signal, libc::SIG_DFL);
+            libc::raise(signal);
+        }
+    } else {
+        RECEIVED.store(signal, Ordering::SeqCst);
+    }
+}
+
+/// The SIGINT and SIGTERM handlers of `install_handlers`, the handlers from before are restored when it is dropped.
+pub struct SignalHandlers {
+    #[cfg(unix)]
+    previous: [(libc::c_int, libc::sigaction); 2],
+}
+
+/// Catches SIGINT and SIGTERM instead of being killed mid-frame, see `take_exit_code`, until the returned guard is dropped.
+/// Only the first signal is caught, a second one restores the terminal and kills the process as usual,
+/// e.g. when the game hangs.
+/// Does nothing on platforms without signals.
+pub fn install_handlers() -> SignalHandlers {
+    #[cfg(unix)]
+    unsafe {
+        let mut action: libc::sigaction = std::mem::zeroed();
+        action.sa_sigaction = on_signal as extern "C" fn(libc::c_int) as libc::sighandler_t;
+        libc::sigemptyset(&mut action.sa_mask);
+        CAUGHT.store(false, Ordering::SeqCst);
+
+        let mut previous = [(libc::SIGINT, std::mem::zeroed()), (libc::SIGTERM, std::mem::zeroed())];
+        for (signal, previous) in &mut previous {
+            libc::sigaction(*signal, &action, previous);
+        }
+        SignalHandlers { previous }
+    }
+    #[cfg(not(unix))]
+    {
+        SignalHandlers {}
+    }
+}
+
+impl Drop for SignalHandlers {
+    fn drop(&mut self) {
+        #[cfg(unix)]
+        for (signal, previous) in &self.previous {
+            unsafe {
+                libc::sigaction(*signal, previous, std::ptr::null_mut());
+            }
+        }
+    }
+}
+
+/// Exit code for a caught signal, 128 + the signal number like a shell reports it, `None` when none was caught
+pub fn take_exit_code() -> Option<i32> {
+    #[cfg(unix)]
+    {
+        match RECEIVED.swap(0, Ordering::SeqCst) {
+            0 => None,
+            signal => Some(128 + signal),
+        }
+    }
+    #[cfg(not(unix))]
+    {
+        None
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    /// Handler currently installed for `signal`
+    fn handler(signal: libc::c_int) -> libc::sighandler_t {
+        unsafe {
+            let mut action: libc::sigaction = std::mem::zeroed();
+            libc::sigaction(signal, std::ptr::null(), &mut action);
+            action.sa_sigaction
+        }
+    }
+
+    #[test]
+    fn caught_signals_become_exit_codes() {
+        let before = handler(libc::SIGTERM);
+        let handlers = install_handlers();
+        assert_eq!(take_exit_code(), None);
+        unsafe { libc::raise(libc::SIGTERM) };
+        assert_eq!(take_exit_code(), Some(128 + libc::SIGTERM));
+        assert_eq!(take_exit_code(), None);
+
+        // the next one would restore the terminal and kill the process
+        assert!(CAUGHT.load(Ordering::SeqCst));
+        assert_eq!(handler(libc::SIGTERM), handler(libc::SIGINT));
+        assert_ne!(handler(libc::SIGINT), before);
+
+        drop(handlers);
+        assert_eq!(handler(libc::SIGTERM), before);
+        assert_eq!(handler(libc::SIGINT), before);
+    }
+}
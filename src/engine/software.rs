@@ -0,0 +1,453 @@
+use super::camera::Camera;
+use super::core::Game;
+use super::edges;
+use super::matrices;
+use super::object::{Object, TextureFilter};
+use super::render::{Readback, RenderOutput, RenderSettings, Renderer};
+use glium::index::PrimitiveType;
+use image::RgbaImage;
+
+/// Values interpolated across a triangle: view space normal (3), texture coordinates (2),
+/// normalized device coordinates (3) and view space depth (1).
+const VARYINGS: usize = 9;
+
+/// Vertex after the vertex stage, in clip space
+#[derive(Copy, Clone, Debug)]
+struct ClipVertex {
+    position: [f32; 4],
+    varyings: [f32; VARYINGS],
+}
+
+/// Renders on the CPU, for machines without an OpenGL driver.
+/// Draws perspective correct textured triangles with a depth test, backface culling and the
+/// Blinn-Phong lighting of `fragment_shader.glsl`, then blends the UI on top like `ui_fragment.glsl`.
+/// Custom shaders in the assets folder are not used.
+pub struct SoftwareRenderer {
+    light: [f32; 3],
+    clear_color: [f32; 4],
+}
+
+impl SoftwareRenderer {
+    pub fn new() -> SoftwareRenderer {
+        SoftwareRenderer::with_settings(&RenderSettings::default())
+    }
+
+    /// Creates a renderer with the clear color and light of `settings`
+    pub fn with_settings(settings: &RenderSettings) -> SoftwareRenderer {
+        let color = settings.clear_color;
+        SoftwareRenderer {
+            light: settings.light,
+            clear_color: [f32::from(color.r) / 255.0, f32::from(color.g) / 255.0, f32::from(color.b) / 255.0, 1.0],
+        }
+    }
+}
+
+impl Default for SoftwareRenderer {
+    fn default() -> Self {
+        SoftwareRenderer::new()
+    }
+}
+
+impl Renderer for SoftwareRenderer {
+    fn render(&mut self, game: &Game, camera: &Camera, width: u32, height: u32, edges: bool) -> RenderOutput {
+        let (w, h) = (width as usize, height as usize);
+        let mut color = vec![self.clear_color; w * h];
+        let mut depth = vec![1.0f32; w * h];
+        let (clear_r, clear_g, clear_b, clear_a) = edges::EDGE_CLEAR_COLOR;
+        let mut normals = edges.then(|| vec![[clear_r, clear_g, clear_b, clear_a]; w * h]);
+
+        let light = normalize(self.light);
+        let view = camera.view_matrix();
+        let perspective = camera.perspective_matrix();
+
+        for object in &game.get_scene().objects {
+            let modelview = crate::mat_mul!(object.model, view);
+            let normal_matrix = normal_matrix(&modelview);
+
+            let vertices: Vec<ClipVertex> = object
+                .vertices
+                .iter()
+                .map(|vertex| {
+                    let (x, y, z) = vertex.position;
+                    let view_position = transform(&modelview, [x, y, z, 1.0]);
+                    let position = transform(&perspective, view_position);
+                    let normal = multiply3(&normal_matrix, vertex.normal);
+                    ClipVertex {
+                        position,
+                        varyings: [
+                            normal[0],
+                            normal[1],
+                            normal[2],
+                            vertex.tex_coords[0],
+                            vertex.tex_coords[1],
+                            position[0] / position[3],
+                            position[1] / position[3],
+                            position[2] / position[3],
+                            view_position[2],
+                        ],
+                    }
+                })
+                .collect();
+
+            for triangle in triangles(object) {
+                let triangle = triangle.map(|index| vertices[index as usize]);
+                rasterize(&clip_near(&triangle), w, h, |index, z, varyings| {
+                    // depth test IfLess with depth writes
+                    if z >= depth[index] {
+                        return;
+                    }
+                    depth[index] = z;
+
+                    let normal = normalize([varyings[0], varyings[1], varyings[2]]);
+                    let diffuse = dot(normal, light).max(0.1);
+                    let diffuse_color = sample(object.image.as_ref(), varyings[3], varyings[4], TextureFilter::Linear);
+
+                    let camera_dir = normalize([-varyings[5], -varyings[6], -varyings[7]]);
+                    let half_direction = normalize([light[0] + camera_dir[0], light[1] + camera_dir[1], light[2] + camera_dir[2]]);
+                    let specular = dot(half_direction, normal).max(0.0).powf(16.0);
+
+                    color[index] = [
+                        diffuse * diffuse_color[0] + specular,
+                        diffuse * diffuse_color[1] + specular,
+                        diffuse * diffuse_color[2] + specular,
+                        1.0,
+                    ];
+
+                    if let Some(normals) = &mut normals {
+                        // same encoding as `edges::EDGE_FRAGMENT_SHADER`
+                        let (znear, zfar) = (matrices::ZNEAR, matrices::ZFAR);
+                        let view_depth = (varyings[8].max(znear) / znear).log2() / (zfar / znear).log2();
+                        normals[index] = [normal[0] * 0.5 + 0.5, normal[1] * 0.5 + 0.5, view_depth, 1.0];
+                    }
+                });
+            }
+        }
+
+        //--------------------------------- UI ---------------------------------
+
+        for ui_elem in &game.get_ui_elems().elems {
+            let vertices: Vec<ClipVertex> = ui_elem
+                .vertices
+                .iter()
+                .map(|vertex| {
+                    let (x, y, z) = vertex.position;
+                    let mut varyings = [0.0; VARYINGS];
+                    varyings[3] = vertex.tex_coords[0];
+                    varyings[4] = vertex.tex_coords[1];
+                    ClipVertex { position: [x * 2.0 - 1.0, y - 1.0, z, 1.0], varyings }
+                })
+                .collect();
+
+            for triangle in triangles(ui_elem) {
+                let triangle = triangle.map(|index| vertices[index as usize]);
+                rasterize(&clip_near(&triangle), w, h, |index, _z, varyings| {
+                    let source = sample(ui_elem.image.as_ref(), varyings[3], varyings[4], ui_elem.texture_filter);
+                    let destination = color[index];
+                    let alpha = source[3];
+                    color[index] = [
+                        source[0] * alpha + destination[0] * (1.0 - alpha),
+                        source[1] * alpha + destination[1] * (1.0 - alpha),
+                        source[2] * alpha + destination[2] * (1.0 - alpha),
+                        source[3] * alpha + destination[3] * (1.0 - alpha),
+                    ];
+                });
+            }
+        }
+
+        let to_readback = |pixels: Vec<[f32; 4]>| Readback {
+            width,
+            height,
+            data: pixels
+                .iter()
+                .flat_map(|pixel| pixel.map(|value| (value.clamp(0.0, 1.0) * 255.0).round() as u8))
+                .collect(),
+        };
+
+        RenderOutput {
+            color: to_readback(color),
+            edges: normals.map(to_readback),
+        }
+    }
+}
+
+/// Indices of the triangles of `object`, following its primitive type
+fn triangles(object: &Object) -> Vec<[u32; 3]> {
+    let indices = &object.indices;
+    match object.primitive {
+        PrimitiveType::TrianglesList => indices.chunks_exact(3).map(|chunk| [chunk[0], chunk[1], chunk[2]]).collect(),
+        PrimitiveType::TriangleFan => (1..indices.len().saturating_sub(1))
+            .map(|i| [indices[0], indices[i], indices[i + 1]])
+            .collect(),
+        PrimitiveType::TriangleStrip => (0..indices.len().saturating_sub(2))
+            .map(|i| {
+                if i % 2 == 0 {
+                    [indices[i], indices[i + 1], indices[i + 2]]
+                } else {
+                    [indices[i + 1], indices[i], indices[i + 2]]
+                }
+            })
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// Clips a triangle against the near plane (z >= -w), returning a convex polygon
+fn clip_near(triangle: &[ClipVertex; 3]) -> Vec<ClipVertex> {
+    let distance = |vertex: &ClipVertex| vertex.position[2] + vertex.position[3];
+    let mut polygon = Vec::with_capacity(4);
+
+    for i in 0..3 {
+        let current = &triangle[i];
+        let next = &triangle[(i + 1) % 3];
+        let (current_distance, next_distance) = (distance(current), distance(next));
+
+        if current_distance >= 0.0 {
+            polygon.push(*current);
+        }
+        if (current_distance >= 0.0) != (next_distance >= 0.0) {
+            let t = current_distance / (current_distance - next_distance);
+            let mut vertex = *current;
+            for (value, (a, b)) in vertex.position.iter_mut().zip(current.position.iter().zip(next.position)) {
+                *value = a + (b - a) * t;
+            }
+            for (value, (a, b)) in vertex.varyings.iter_mut().zip(current.varyings.iter().zip(next.varyings)) {
+                *value = a + (b - a) * t;
+            }
+            polygon.push(vertex);
+        }
+    }
+
+    polygon
+}
+
+/// Rasterizes a convex polygon in clip space onto a `width` x `height` target (bottom row first).
+/// Triangles that are clockwise on screen are culled. Calls `fragment` with the pixel index,
+/// the window space depth and the perspective correct varyings for every covered pixel center.
+fn rasterize<F>(polygon: &[ClipVertex], width: usize, height: usize, mut fragment: F)
+where
+    F: FnMut(usize, f32, &[f32; VARYINGS]),
+{
+    if polygon.len() < 3 {
+        return;
+    }
+
+    // window coordinates and 1/w for every vertex
+    let window: Vec<[f32; 4]> = polygon
+        .iter()
+        .map(|vertex| {
+            let [x, y, z, w] = vertex.position;
+            [
+                (x / w + 1.0) * 0.5 * width as f32,
+                (y / w + 1.0) * 0.5 * height as f32,
+                (z / w) * 0.5 + 0.5,
+                1.0 / w,
+            ]
+        })
+        .collect();
+
+    for i in 1..polygon.len() - 1 {
+        let (a, b, c) = (window[0], window[i], window[i + 1]);
+        let area = edge_function(a, b, c[0], c[1]);
+        if area <= 0.0 {
+            continue;
+        }
+
+        let min_x = a[0].min(b[0]).min(c[0]).floor().max(0.0) as usize;
+        let min_y = a[1].min(b[1]).min(c[1]).floor().max(0.0) as usize;
+        let max_x = (a[0].max(b[0]).max(c[0]).ceil().max(0.0) as usize).min(width);
+        let max_y = (a[1].max(b[1]).max(c[1]).ceil().max(0.0) as usize).min(height);
+
+        let (va, vb, vc) = (&polygon[0].varyings, &polygon[i].varyings, &polygon[i + 1].varyings);
+        for y in min_y..max_y {
+            for x in min_x..max_x {
+                let (px, py) = (x as f32 + 0.5, y as f32 + 0.5);
+                let wa = edge_function(b, c, px, py) / area;
+                let wb = edge_function(c, a, px, py) / area;
+                let wc = edge_function(a, b, px, py) / area;
+                if wa < 0.0 || wb < 0.0 || wc < 0.0 {
+                    continue;
+                }
+
+                let z = wa * a[2] + wb * b[2] + wc * c[2];
+                if !(0.0..=1.0).contains(&z) {
+                    continue;
+                }
+
+                // interpolate in clip space, like OpenGL does for smooth varyings
+                let (pa, pb, pc) = (wa * a[3], wb * b[3], wc * c[3]);
+                let sum = pa + pb + pc;
+                let mut varyings = [0.0; VARYINGS];
+                for (j, value) in varyings.iter_mut().enumerate() {
+                    *value = (pa * va[j] + pb * vb[j] + pc * vc[j]) / sum;
+                }
+
+                fragment(y * width + x, z, &varyings);
+            }
+        }
+    }
+}
+
+/// Twice the signed area of the triangle `a`, `b`, (`x`, `y`), positive when counter-clockwise
+fn edge_function(a: [f32; 4], b: [f32; 4], x: f32, y: f32) -> f32 {
+    (b[0] - a[0]) * (y - a[1]) - (b[1] - a[1]) * (x - a[0])
+}
+
+/// Samples `image` (top row first) at texture coordinates `u`, `v` with repeat wrapping, white without an image
+fn sample(image: Option<&RgbaImage>, u: f32, v: f32, filter: TextureFilter) -> [f32; 4] {
+    let image = match image {
+        Some(image) if image.width() > 0 && image.height() > 0 => image,
+        _ => return [1.0; 4],
+    };
+    let (width, height) = (image.width() as i64, image.height() as i64);
+    let texel = |x: i64, y: i64| {
+        // v = 0 is the bottom row of the texture
+        let pixel = image.get_pixel(x.rem_euclid(width) as u32, (height - 1 - y.rem_euclid(height)) as u32);
+        pixel.0.map(|value| f32::from(value) / 255.0)
+    };
+
+    let x = u * width as f32;
+    let y = v * height as f32;
+    match filter {
+        TextureFilter::Nearest => texel(x.floor() as i64, y.floor() as i64),
+        TextureFilter::Linear => {
+            let (x, y) = (x - 0.5, y - 0.5);
+            let (x0, y0) = (x.floor(), y.floor());
+            let (fx, fy) = (x - x0, y - y0);
+            let (x0, y0) = (x0 as i64, y0 as i64);
+            let (t00, t10, t01, t11) = (texel(x0, y0), texel(x0 + 1, y0), texel(x0, y0 + 1), texel(x0 + 1, y0 + 1));
+            let mut result = [0.0; 4];
+            for (i, value) in result.iter_mut().enumerate() {
+                let bottom = t00[i] + (t10[i] - t00[i]) * fx;
+                let top = t01[i] + (t11[i] - t01[i]) * fx;
+                *value = bottom + (top - bottom) * fy;
+            }
+            result
+        }
+    }
+}
+
+/// `matrix` * `vector`, for column major matrices as passed to the shaders
+fn transform(matrix: &[[f32; 4]; 4], vector: [f32; 4]) -> [f32; 4] {
+    let mut result = [0.0; 4];
+    for (row, value) in result.iter_mut().enumerate() {
+        *value = (0..4).map(|column| matrix[column][row] * vector[column]).sum();
+    }
+    result
+}
+
+/// `transpose(inverse(mat3(modelview)))` of the vertex shader, as three columns
+fn normal_matrix(modelview: &[[f32; 4]; 4]) -> [[f32; 3]; 3] {
+    let column = |i: usize| [modelview[i][0], modelview[i][1], modelview[i][2]];
+    let (a0, a1, a2) = (column(0), column(1), column(2));
+    let (c0, c1, c2) = (cross(a1, a2), cross(a2, a0), cross(a0, a1));
+    let determinant = dot(a0, c0);
+    if determinant == 0.0 {
+        return [[0.0; 3]; 3];
+    }
+    [c0, c1, c2].map(|column| column.map(|value| value / determinant))
+}
+
+/// `matrix` * `vector` for a matrix given as three columns
+fn multiply3(matrix: &[[f32; 3]; 3], vector: [f32; 3]) -> [f32; 3] {
+    let mut result = [0.0; 3];
+    for (row, value) in result.iter_mut().enumerate() {
+        *value = (0..3).map(|column| matrix[column][row] * vector[column]).sum();
+    }
+    result
+}
+
+fn dot(a: [f32; 3], b: [f32; 3]) -> f32 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+fn cross(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [a[1] * b[2] - a[2] * b[1], a[2] * b[0] - a[0] * b[2], a[0] * b[1] - a[1] * b[0]]
+}
+
+fn normalize(v: [f32; 3]) -> [f32; 3] {
+    let length = dot(v, v).sqrt();
+    if length == 0.0 {
+        v
+    } else {
+        v.map(|value| value / length)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::object::Vertex;
+    use crate::engine::scene::Scene;
+
+    fn game() -> Game {
+        let camera = Camera::new([0.0, 0.0, 0.0], [0.0, 0.0, 0.0], 0.05, 0.05, (64, 32));
+        let mut game = Game::new(camera, String::new());
+        game.add_scene(Scene::new());
+        game
+    }
+
+    /// A white quad facing the camera at `z`, `size` units wide
+    fn quad(z: f32, size: f32, counter_clockwise: bool) -> Object {
+        let vertex = |x: f32, y: f32| Vertex {
+            position: (x * size, y * size, z),
+            normal: [0.0, 0.0, -1.0],
+            tex_coords: [0.0, 0.0],
+        };
+        let vertices = vec![vertex(-1.0, -1.0), vertex(1.0, -1.0), vertex(1.0, 1.0), vertex(-1.0, 1.0)];
+        let indices = if counter_clockwise { vec![0, 2, 1, 0, 3, 2] } else { vec![0, 1, 2, 0, 2, 3] };
+        Object::from_mesh(
+            vertices,
+            indices,
+            PrimitiveType::TrianglesList,
+            None,
+            TextureFilter::Linear,
+            crate::engine::matrices::identity_matrix(),
+            Vec::new(),
+        )
+    }
+
+    fn center(output: &RenderOutput) -> [u8; 4] {
+        let (width, height) = (output.color.width as usize, output.color.height as usize);
+        let index = ((height / 2) * width + width / 2) * 4;
+        [0, 1, 2, 3].map(|i| output.color.data[index + i])
+    }
+
+    #[test]
+    fn empty_scene_is_the_clear_color() {
+        let output = SoftwareRenderer::new().render(&game(), &game().camera, 8, 4, false);
+        assert_eq!(output.color.data.len(), 8 * 4 * 4);
+        assert!(output.color.data.chunks(4).all(|pixel| pixel == [105, 109, 219, 255]));
+        assert_eq!(output.edges, None);
+    }
+
+    #[test]
+    fn front_faces_are_lit_and_back_faces_culled() {
+        let renderer = &mut SoftwareRenderer::new();
+
+        let mut front = game();
+        front.get_scene_mut().add_object(quad(2.0, 1.0, false));
+        let lit = center(&renderer.render(&front, &front.camera, 16, 8, false));
+        assert_ne!(lit, [105, 109, 219, 255]);
+        assert_eq!(lit[0], lit[1]);
+
+        let mut back = game();
+        back.get_scene_mut().add_object(quad(2.0, 1.0, true));
+        assert_eq!(center(&renderer.render(&back, &back.camera, 16, 8, false)), [105, 109, 219, 255]);
+    }
+
+    #[test]
+    fn nearer_triangles_win_the_depth_test() {
+        let renderer = &mut SoftwareRenderer::new();
+
+        let mut near_first = game();
+        near_first.get_scene_mut().add_object(quad(2.0, 1.0, false));
+        near_first.get_scene_mut().add_object(quad(4.0, 2.0, false));
+        let mut far_first = game();
+        far_first.get_scene_mut().add_object(quad(4.0, 2.0, false));
+        far_first.get_scene_mut().add_object(quad(2.0, 1.0, false));
+
+        let edges_near_first = renderer.render(&near_first, &near_first.camera, 16, 8, true).edges.unwrap();
+        let edges_far_first = renderer.render(&far_first, &far_first.camera, 16, 8, true).edges.unwrap();
+        assert_eq!(edges_near_first, edges_far_first);
+    }
+}
@@ -0,0 +1,360 @@
+use super::ascii_render::{CellMode, FrameSync, GlyphSet, OutputSettings};
+use super::palette::ColorDepth;
+use std::io::{IsTerminal, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Once;
+
+/// Switches to the alternate screen, hides the cursor and disables line wrap
+const ENTER_SESSION: &str = "\x1b[?1049h\x1b[?25l\x1b[?7l";
+/// Undoes `ENTER_SESSION` and resets colors
+const LEAVE_SESSION: &str = "\x1b[0m\x1b[?7h\x1b[?25h\x1b[?1049l";
+
+/// A `TerminalSession` is alive and the terminal has to be restored
+static SESSION_ACTIVE: AtomicBool = AtomicBool::new(false);
+static PANIC_HOOK: Once = Once::new();
+
+/// What the attached terminal is able to display.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TerminalCapabilities {
+    pub color_depth: ColorDepth,
+    /// The locale uses UTF-8, so glyphs outside of ASCII can be printed
+    pub unicode: bool,
+    /// Both stdin and stdout are attached to a terminal
+    pub tty: bool,
+    /// Attributes the terminal reported in its DA1 (primary device attributes) reply, if it was queried
+    pub device_attributes: Option<Vec<u32>>,
+    /// The terminal reported support for synchronized output (DEC private mode 2026), see `FrameSync`
+    pub synchronized_output: bool,
+}
+
+impl TerminalCapabilities {
+    /// Detects capabilities from the environment and, when a TTY is attached, by querying the terminal.
+    pub fn detect() -> TerminalCapabilities {
+        let mut capabilities = TerminalCapabilities::from_env();
+        if capabilities.tty && capabilities.color_depth != ColorDepth::Monochrome {
+            capabilities.query_terminal();
+        }
+        capabilities
+    }
+
+    /// Detects capabilities from `COLORTERM`, `TERM`, `NO_COLOR` and the locale variables only.
+    pub fn from_env() -> TerminalCapabilities {
+        let tty = std::io::stdin().is_terminal() && std::io::stdout().is_terminal();
+        TerminalCapabilities::from_vars(|name| std::env::var(name).ok(), tty)
+    }
+
+    /// Detects capabilities from variables returned by `var`, which returns `None` for unset variables,
+    /// for a terminal that is attached to stdin and stdout when `tty` is set.
+    pub fn from_vars<F>(var: F, tty: bool) -> TerminalCapabilities
+    where
+        F: Fn(&str) -> Option<String>,
+    {
+        let term = var("TERM").unwrap_or_default();
+        let colorterm = var("COLORTERM").unwrap_or_default().to_lowercase();
+        let no_color = var("NO_COLOR").is_some_and(|value| !value.is_empty());
+
+        let color_depth = if no_color || term == "dumb" {
+            ColorDepth::Monochrome
+        } else if colorterm == "truecolor" || colorterm == "24bit" || term.ends_with("-direct") {
+            ColorDepth::TrueColor
+        } else if term.contains("256color") {
+            ColorDepth::Ansi256
+        } else {
+            ColorDepth::Ansi16
+        };
+
+        // the first non-empty of LC_ALL, LC_CTYPE and LANG decides the character set
+        let locale = ["LC_ALL", "LC_CTYPE", "LANG"]
+            .iter()
+            .filter_map(|name| var(name))
+            .find(|value| !value.is_empty())
+            .unwrap_or_default()
+            .to_lowercase();
+        let unicode = locale.contains("utf-8") || locale.contains("utf8");
+
+        TerminalCapabilities {
+            color_depth,
+            unicode,
+            tty,
+            device_attributes: None,
+            synchronized_output: false,
+        }
+    }
+
+    /// Output settings that make the most of these capabilities
+    pub fn output_settings(&self) -> OutputSettings {
+        let cell_mode = match (self.unicode, self.color_depth) {
+            (false, _) => CellMode::Ascii,
+            (true, ColorDepth::Monochrome) => CellMode::Braille,
+            (true, _) => CellMode::HalfBlock,
+        };
+
+        OutputSettings {
+            cell_mode,
+            color_depth: self.color_depth,
+            glyph_set: if self.unicode { GlyphSet::Unicode } else { GlyphSet::Ascii },
+            ..Default::default()
+        }
+    }
+
+    /// How frames are best written to this terminal
+    pub fn frame_sync(&self) -> FrameSync {
+        if self.synchronized_output {
+            FrameSync::Synchronized
+        } else {
+            FrameSync::SingleWrite
+        }
+    }
+
+    /// Asks the terminal for truecolor support (DECRQSS), synchronized output (DECRQM 2026) and its device attributes (DA1).
+    /// Terminals that do not answer within the timeout leave the capabilities unchanged.
+    #[cfg(unix)]
+    fn query_terminal(&mut self) {
+        use std::os::unix::io::AsRawFd;
+
+        let fd = std::io::stdin().as_raw_fd();
+        let mut original: libc::termios = unsafe { std::mem::zeroed() };
+        if unsafe { libc::tcgetattr(fd, &mut original) } != 0 {
+            return;
+        }
+        let mut raw = original;
+        raw.c_lflag &= !(libc::ICANON | libc::ECHO);
+        raw.c_cc[libc::VMIN] = 0;
+        raw.c_cc[libc::VTIME] = 0;
+        if unsafe { libc::tcsetattr(fd, libc::TCSANOW, &raw) } != 0 {
+            return;
+        }
+
+        // set a truecolor foreground and ask for it back, ask for mode 2026, then send DA1 which every terminal answers last
+        let mut out = std::io::stdout();
+        let _ = write!(out, "\x1b[38;2;1;2;3m\x1bP$qm\x1b\\\x1b[0m\x1b[?2026$p\x1b[c");
+        let _ = out.flush();
+
+        let mut reply = Vec::new();
+        let deadline = std::time::Instant::now() + std::time::Duration::from_millis(200);
+        while !da1_complete(&reply) {
+            let remaining = deadline.saturating_duration_since(std::time::Instant::now());
+            if remaining.is_zero() {
+                break;
+            }
+            let mut poll_fd = libc::pollfd { fd, events: libc::POLLIN, revents: 0 };
+            if unsafe { libc::poll(&mut poll_fd, 1, remaining.as_millis() as libc::c_int) } <= 0 {
+                break;
+            }
+            let mut buffer = [0u8; 256];
+            let read = unsafe { libc::read(fd, buffer.as_mut_ptr() as *mut libc::c_void, buffer.len()) };
+            if read <= 0 {
+                break;
+            }
+            reply.extend_from_slice(&buffer[..read as usize]);
+        }
+
+        // replies that arrive too late must not end up in the game's input or the shell
+        unsafe {
+            libc::tcflush(fd, libc::TCIFLUSH);
+            libc::tcsetattr(fd, libc::TCSANOW, &original);
+        }
+
+        self.apply_reply(&reply);
+    }
+
+    /// Takes what the terminal answered to the queries of `query_terminal` into the capabilities
+    #[cfg(unix)]
+    fn apply_reply(&mut self, reply: &[u8]) {
+        let reply = String::from_utf8_lossy(reply);
+        if let Some(start) = reply.find("\x1bP1$r") {
+            // the setting ends at ST, whatever follows belongs to the other replies
+            let setting = &reply[start..];
+            let setting = setting.find("\x1b\\").map_or(setting, |end| &setting[..end]);
+            if setting.contains("1:2:3") || setting.contains("1;2;3") {
+                self.color_depth = ColorDepth::TrueColor;
+            }
+        }
+        // 1 and 2 mean the mode is set or reset, 3 that it is always set, so the terminal knows it
+        self.synchronized_output = ["1", "2", "3"]
+            .iter()
+            .any(|state| reply.contains(&format!("\x1b[?2026;{}$y", state)));
+        // the DECRQM reply starts the same way as DA1, so only take the one ending in 'c'
+        for sequence in reply.split("\x1b[?").skip(1) {
+            if let Some(end) = sequence.find(|c: char| !c.is_ascii_digit() && c != ';') {
+                if sequence[end..].starts_with('c') {
+                    self.device_attributes = Some(
+                        sequence[..end]
+                            .split(';')
+                            .filter_map(|value| value.parse().ok())
+                            .collect(),
+                    );
+                }
+            }
+        }
+    }
+
+    #[cfg(not(unix))]
+    fn query_terminal(&mut self) {}
+}
+
+/// Checks if `reply` contains a complete DA1 answer (`ESC [ ? ... c`).
+#[cfg(unix)]
+fn da1_complete(reply: &[u8]) -> bool {
+    reply.windows(3).enumerate().any(|(start, window)| {
+        window == b"\x1b[?"
+            && reply[start + 3..]
+                .iter()
+                .find(|&&byte| !byte.is_ascii_digit() && byte != b';')
+                .is_some_and(|&byte| byte == b'c')
+    })
+}
+
+/// Keeps the terminal on the alternate screen with a hidden cursor and without line wrap while it is alive.
+/// Dropping it restores the shell's screen, and so does a panic before its message is printed.
+pub struct TerminalSession {
+    _private: (),
+}
+
+impl TerminalSession {
+    /// Enters the alternate screen on stdout and installs the panic hook restoring it
+    pub fn enter() -> std::io::Result<TerminalSession> {
+        PANIC_HOOK.call_once(|| {
+            let previous = std::panic::take_hook();
+            std::panic::set_hook(Box::new(move |info| {
+                leave_session();
+                previous(info);
+            }));
+        });
+
+        let mut out = std::io::stdout();
+        out.write_all(ENTER_SESSION.as_bytes())?;
+        out.flush()?;
+        SESSION_ACTIVE.store(true, Ordering::SeqCst);
+        Ok(TerminalSession { _private: () })
+    }
+}
+
+impl Drop for TerminalSession {
+    fn drop(&mut self) {
+        leave_session();
+    }
+}
+
+/// Restores the terminal once, if a session is active.
+/// Writes to fd 1 without locking stdout, the panic hook may run while another thread holds the lock,
+/// e.g. a `TerminalFrameBuffer` drawing to stdout. On unix it is async-signal-safe, so signal handlers may call it.
+pub(crate) fn leave_session() {
+    if SESSION_ACTIVE.swap(false, Ordering::SeqCst) {
+        #[cfg(unix)]
+        {
+            let mut bytes = LEAVE_SESSION.as_bytes();
+            while !bytes.is_empty() {
+                let written = unsafe { libc::write(libc::STDOUT_FILENO, bytes.as_ptr().cast(), bytes.len()) };
+                if written > 0 {
+                    bytes = &bytes[written as usize..];
+                } else if written == 0 || std::io::Error::last_os_error().kind() != std::io::ErrorKind::Interrupted {
+                    break;
+                }
+            }
+        }
+        #[cfg(not(unix))]
+        {
+            let mut out = std::io::stdout();
+            let _ = out.write_all(LEAVE_SESSION.as_bytes());
+            let _ = out.flush();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn capabilities(vars: &[(&str, &str)]) -> TerminalCapabilities {
+        TerminalCapabilities::from_vars(
+            |name| vars.iter().find(|(var, _)| *var == name).map(|(_, value)| value.to_string()),
+            true,
+        )
+    }
+
+    #[test]
+    fn color_depth_follows_the_environment() {
+        let cases: [(&[(&str, &str)], ColorDepth); 8] = [
+            (&[("TERM", "xterm-256color"), ("COLORTERM", "truecolor")], ColorDepth::TrueColor),
+            (&[("TERM", "xterm"), ("COLORTERM", "24BIT")], ColorDepth::TrueColor),
+            (&[("TERM", "xterm-direct")], ColorDepth::TrueColor),
+            (&[("TERM", "screen-256color")], ColorDepth::Ansi256),
+            (&[("TERM", "xterm")], ColorDepth::Ansi16),
+            (&[], ColorDepth::Ansi16),
+            (&[("TERM", "dumb"), ("COLORTERM", "truecolor")], ColorDepth::Monochrome),
+            (&[("TERM", "xterm-256color"), ("NO_COLOR", "1")], ColorDepth::Monochrome),
+        ];
+        for (vars, depth) in cases {
+            assert_eq!(capabilities(vars).color_depth, depth, "{:?}", vars);
+        }
+        assert_eq!(capabilities(&[("TERM", "xterm"), ("NO_COLOR", "")]).color_depth, ColorDepth::Ansi16);
+    }
+
+    #[test]
+    fn unicode_follows_the_first_set_locale() {
+        let cases: [(&[(&str, &str)], bool); 6] = [
+            (&[("LANG", "en_US.UTF-8")], true),
+            (&[("LC_CTYPE", "de_DE.utf8")], true),
+            (&[("LC_ALL", "C"), ("LANG", "en_US.UTF-8")], false),
+            (&[("LC_ALL", ""), ("LC_CTYPE", "C.UTF-8"), ("LANG", "C")], true),
+            (&[("LANG", "C")], false),
+            (&[], false),
+        ];
+        for (vars, unicode) in cases {
+            assert_eq!(capabilities(vars).unicode, unicode, "{:?}", vars);
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn terminal_replies_are_parsed() {
+        let mut xterm = capabilities(&[("TERM", "xterm-256color")]);
+        xterm.apply_reply(b"\x1bP1$r0;38:2::1:2:3m\x1b\\\x1b[?2026;2$y\x1b[?64;1;2;6;9;15;22c");
+        assert_eq!(xterm.color_depth, ColorDepth::TrueColor);
+        assert!(xterm.synchronized_output);
+        assert_eq!(xterm.device_attributes, Some(vec![64, 1, 2, 6, 9, 15, 22]));
+        assert_eq!(xterm.frame_sync(), FrameSync::Synchronized);
+
+        // an invalid setting, a mode the terminal does not know and only the DA1 reply taken as attributes
+        let mut old = capabilities(&[("TERM", "xterm-256color")]);
+        old.apply_reply(b"\x1bP0$r\x1b\\\x1b[?2026;0$y\x1b[?1;2c");
+        assert_eq!(old.color_depth, ColorDepth::Ansi256);
+        assert!(!old.synchronized_output);
+        assert_eq!(old.device_attributes, Some(vec![1, 2]));
+        assert_eq!(old.frame_sync(), FrameSync::SingleWrite);
+
+        // permanently set
+        let mut kitty = capabilities(&[]);
+        kitty.apply_reply(b"\x1b[?2026;3$y\x1b[?62;c");
+        assert!(kitty.synchronized_output);
+        assert_eq!(kitty.device_attributes, Some(vec![62]));
+
+        // a valid setting without the color, and a DA1 reply that happens to contain the same numbers
+        let mut ansi = capabilities(&[("TERM", "xterm")]);
+        ansi.apply_reply(b"\x1bP1$r0m\x1b\\\x1b[?1;2;3c");
+        assert_eq!(ansi.color_depth, ColorDepth::Ansi16);
+        assert_eq!(ansi.device_attributes, Some(vec![1, 2, 3]));
+
+        let mut silent = capabilities(&[("TERM", "xterm")]);
+        silent.apply_reply(b"");
+        assert_eq!(silent.color_depth, ColorDepth::Ansi16);
+        assert_eq!(silent.device_attributes, None);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn only_the_da1_reply_completes_the_query() {
+        assert!(!da1_complete(b""));
+        assert!(!da1_complete(b"\x1bP1$r0m\x1b\\\x1b[?2026;2$y"));
+        assert!(!da1_complete(b"\x1b[?2026;2$y\x1b[?64;1"));
+        assert!(da1_complete(b"\x1b[?2026;2$y\x1b[?64;1;2c"));
+        assert!(da1_complete(b"\x1b[?6c"));
+    }
+
+    #[test]
+    fn tty_is_taken_as_given() {
+        assert!(capabilities(&[]).tty);
+        assert!(!TerminalCapabilities::from_vars(|_| None, false).tty);
+    }
+}
@@ -1,11 +1,13 @@
 use super::object::Vertex;
-use super::object::{Object, TextureFilter};
+use super::error::EngineError;
+use super::object::{load_image, Object, TextureFilter, Upload};
+use glium::index::PrimitiveType;
 use glium::texture::RawImage2d;
 use glium::texture::Texture2d;
 use std::path::Path;
 
 use fontdue::Font;
-use image::ImageBuffer;
+use image::{ImageBuffer, RgbaImage};
 
 #[allow(dead_code)]
 /// writes text to the screen at the given origin with the given size and font
@@ -15,14 +17,14 @@ pub fn draw_text(
     text: &str,
     size: f32,
     font: &Font,
-    display: &glium::Display,
-) -> Vec<Object> {
+    display: &(impl Upload + ?Sized),
+) -> Result<Vec<Object>, EngineError> {
     let mut objects: Vec<Object> = Vec::new();
 
     //we are using fontdue to render text
     let mut x_offset = 0.0;
 
-    for (_i, character) in text.chars().enumerate() {
+    for character in text.chars() {
         // println!("character: {} origin_x {} origin_y {}", character, origin_x, origin_y);
         let size = size * 5.0;
 
@@ -35,11 +37,6 @@ pub fn draw_text(
             *pixel = image::Rgba([255, 255, 255, alpha]);
         }
 
-        let image_dimensions = image.dimensions();
-        let image = RawImage2d::from_raw_rgba_reversed(&image.into_raw(), image_dimensions);
-
-        let texture = Texture2d::new(display, image).unwrap();
-
         let actual_width = metrics.bounds.width / 100.;
         let actual_height = metrics.bounds.height / 100.;
 
@@ -77,10 +74,6 @@ pub fn draw_text(
 
         let indices = vec![0, 1, 2, 2, 3, 0];
 
-        let ib =
-            glium::IndexBuffer::new(display, glium::index::PrimitiveType::TriangleFan, &indices)
-                .unwrap();
-
         let model = [
             [1.0, 0.0, 0.0, 0.0],
             [0.0, -1.0, 0.0, 0.0],
@@ -89,20 +82,21 @@ pub fn draw_text(
         ];
 
 
-        let object = Object {
-            vb: glium::VertexBuffer::new(display, &vertices).unwrap(),
+        let mut object = Object::from_mesh(
             vertices,
-            ib,
-            texture,
-            texture_filter: TextureFilter::Nearest,
+            indices,
+            PrimitiveType::TriangleFan,
+            Some(image),
+            TextureFilter::Nearest,
             model,
-            tags: vec!["ui".to_string()]
-        };
+            vec!["ui".to_string()]
+        );
+        display.upload(&mut object)?;
 
         objects.push(object);
     }
 
-    objects
+    Ok(objects)
 }
 
 #[allow(dead_code)]
@@ -114,8 +108,17 @@ pub fn draw_rect_tex(
     height: f32,
     texture: Texture2d,
     texture_filter: TextureFilter,
-    display: &glium::Display,
-) -> Object {
+    display: &(impl Upload + ?Sized),
+) -> Result<Object, EngineError> {
+    // keep a copy in memory, GPU textures are stored bottom row first
+    let pixels: RawImage2d<u8> = texture
+        .read_to_pixel_buffer()
+        .read_as_texture_2d()
+        .map_err(|error| EngineError::Context(error.to_string()))?;
+    let image: RgbaImage = ImageBuffer::from_raw(pixels.width, pixels.height, pixels.data.into_owned())
+        .ok_or_else(|| EngineError::Context("texture read back with the wrong size".to_string()))?;
+    let image = image::imageops::flip_vertical(&image);
+
     let height = height * 2.0;
 
     let y = 2.0 - y * 2.0 - height;
@@ -145,11 +148,6 @@ pub fn draw_rect_tex(
 
     let indices = vec![0, 1, 2, 2, 3, 0];
 
-    let vb = glium::VertexBuffer::new(display, &vertices).unwrap();
-
-    let ib = glium::IndexBuffer::new(display, glium::index::PrimitiveType::TriangleFan, &indices)
-        .unwrap();
-
     let model = [
         [1.0, 0.0, 0.0, 0.0],
         [0.0, 1.0, 0.0, 0.0],
@@ -158,15 +156,17 @@ pub fn draw_rect_tex(
     ];
 
 
-    Object {
-        model,
-        texture,
-        texture_filter,
+    let mut object = Object::from_mesh(
         vertices,
-        vb,
-        ib,
-        tags: vec!["ui".to_string()]
-    }
+        indices,
+        PrimitiveType::TriangleFan,
+        Some(image),
+        texture_filter,
+        model,
+        vec!["ui".to_string()]
+    );
+    display.upload(&mut object)?;
+    Ok(object)
 }
 
 #[allow(dead_code)]
@@ -178,12 +178,9 @@ pub fn draw_rect(
     height: f32,
     texture_path: &str,
     texture_filter: TextureFilter,
-    display: &glium::Display,
-) -> Object {
-    let image = image::open(Path::new(texture_path)).unwrap().to_rgba8();
-    let image_dimensions = image.dimensions();
-    let image = RawImage2d::from_raw_rgba_reversed(&image.into_raw(), image_dimensions);
-    let texture = Texture2d::new(display, image).unwrap();
+    display: &(impl Upload + ?Sized),
+) -> Result<Object, EngineError> {
+    let image = load_image(Path::new(texture_path))?;
 
     let height = height * 2.0;
 
@@ -215,11 +212,6 @@ pub fn draw_rect(
 
     let indices = vec![0, 1, 2, 2, 3, 0];
 
-    let vb = glium::VertexBuffer::new(display, &vertices).unwrap();
-
-    let ib = glium::IndexBuffer::new(display, glium::index::PrimitiveType::TriangleFan, &indices)
-        .unwrap();
-
     let model = [
         [1.0, 0.0, 0.0, 0.0],
         [0.0, 1.0, 0.0, 0.0],
@@ -228,13 +220,15 @@ pub fn draw_rect(
     ];
 
 
-    Object {
-        model,
-        texture,
-        texture_filter,
+    let mut object = Object::from_mesh(
         vertices,
-        vb,
-        ib,
-        tags: vec!["ui".to_string()]
-    }
+        indices,
+        PrimitiveType::TriangleFan,
+        Some(image),
+        texture_filter,
+        model,
+        vec!["ui".to_string()]
+    );
+    display.upload(&mut object)?;
+    Ok(object)
 }